@@ -0,0 +1,270 @@
+use anyhow::Result;
+use log::warn;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::sync::{Mutex, MutexGuard};
+
+use super::{Lock, StorageBackend};
+use crate::history::{self, History, RunRecord};
+use crate::migrations;
+use crate::state::State;
+use crate::storage::{self, Event, Storage};
+
+// Serializes read-modify-write cycles on history files within this process;
+// `history.lock` does the same across processes
+static HISTORY_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// JSON files, split between the config and the state directory:
+///
+/// - `events.json` (config): task definitions, versioned (see
+///   [`migrations`]), with the previous version kept in `events.json.bak`
+/// - `state.json` (state): runtime state, written only by the daemon
+/// - `history/<slug>.json` (state): run records of each task
+#[derive(Debug, Clone)]
+pub struct JsonBackend {
+    config_dir: PathBuf,
+    state_dir: PathBuf,
+}
+
+/// On-disk layout of `events.json`; see [`migrations`] for older layouts.
+#[derive(Serialize)]
+struct Document<'a> {
+    version: u64,
+    events: &'a [Event],
+}
+
+impl JsonBackend {
+    pub fn new(config_dir: PathBuf, state_dir: PathBuf) -> Self {
+        JsonBackend {
+            config_dir,
+            state_dir,
+        }
+    }
+
+    /// Read the raw `events.json` document, falling back to its backup if it
+    /// is corrupt.
+    async fn read_definitions(&self) -> Result<Value> {
+        let path = self.events_path();
+
+        if !path.exists() {
+            // Create directory if it doesn't exist
+            fs::create_dir_all(&self.config_dir).await?;
+            return Ok(serde_json::to_value(document(&[]))?);
+        }
+
+        let error = match read(&path).await {
+            Ok(doc) => return Ok(doc),
+            Err(e) => e,
+        };
+
+        // A torn or corrupt file: fall back to the copy from the previous save
+        let backup = self.backup_path();
+        match read(&backup).await {
+            Ok(doc) => {
+                warn!(
+                    "{} is unreadable ({}); using backup {}",
+                    path.display(),
+                    error,
+                    backup.display()
+                );
+                Ok(doc)
+            }
+            Err(_) => Err(anyhow::anyhow!(
+                "Failed to read {}: {}",
+                path.display(),
+                error
+            )),
+        }
+    }
+
+    fn events_path(&self) -> PathBuf {
+        self.config_dir.join("events.json")
+    }
+
+    fn backup_path(&self) -> PathBuf {
+        self.config_dir.join("events.json.bak")
+    }
+
+    fn state_path(&self) -> PathBuf {
+        self.state_dir.join("state.json")
+    }
+
+    /// Take the cross-process lock on the run history, after the in-process
+    /// one so tasks of this process don't tie up blocking threads waiting.
+    async fn lock_history(&self) -> Result<(MutexGuard<'static, ()>, Lock)> {
+        let guard = HISTORY_LOCK.lock().await;
+        let lock = super::lock_file(self.state_dir.join("history.lock")).await?;
+        Ok((guard, lock))
+    }
+
+    fn history_path(&self, slug: &str) -> PathBuf {
+        self.state_dir
+            .join("history")
+            .join(format!("{}.json", history::file_name(slug)))
+    }
+}
+
+impl StorageBackend for JsonBackend {
+    async fn upgrade(&self) -> Result<()> {
+        let doc = self.read_definitions().await?;
+        if migrations::schema_version(&doc)? == migrations::SCHEMA_VERSION {
+            return Ok(());
+        }
+
+        // Written by an older version; upgrade it under the lock, unless
+        // another process got there first
+        let _lock = self.lock().await?;
+        self.load_tasks().await.map(|_| ())
+    }
+
+    async fn lock(&self) -> Result<Lock> {
+        super::lock_file(self.config_dir.join("events.json.lock")).await
+    }
+
+    async fn load_tasks(&self) -> Result<Storage> {
+        let mut doc = self.read_definitions().await?;
+        if migrations::schema_version(&doc)? == migrations::SCHEMA_VERSION {
+            return Ok(serde_json::from_value(doc)?);
+        }
+
+        // Written by an older version, possibly since the backend was opened
+        let mut state = self.load_state().await?;
+        migrations::migrate(&mut doc, &mut state)?;
+        let storage: Storage = serde_json::from_value(doc)?;
+
+        // Migrations may move data into `state.json`, so both files are written
+        self.save_state(&state).await?;
+        self.save_tasks(&storage).await?;
+        Ok(storage)
+    }
+
+    /// Write the file atomically, keeping the previous version as a backup.
+    async fn save_tasks(&self, storage: &Storage) -> Result<()> {
+        let path = self.events_path();
+        fs::create_dir_all(&self.config_dir).await?;
+
+        // Only a readable file is worth keeping; never replace a good backup
+        // with a corrupt one
+        if read(&path).await.is_ok() {
+            fs::copy(&path, self.backup_path()).await?;
+        }
+
+        let content = serde_json::to_string_pretty(&document(&storage.events))?;
+        storage::write_atomic(&path, content.as_bytes()).await
+    }
+
+    /// A missing or unreadable state file starts out empty since everything
+    /// in it can be rebuilt; an unreadable one is first moved aside to
+    /// `state.json.corrupt`, so the next save doesn't destroy it.
+    async fn load_state(&self) -> Result<State> {
+        let path = self.state_path();
+
+        if !path.exists() {
+            return Ok(State::default());
+        }
+
+        let content = fs::read_to_string(&path).await?;
+        match serde_json::from_str(&content) {
+            Ok(state) => Ok(state),
+            Err(e) => {
+                let corrupt = self.state_dir.join("state.json.corrupt");
+                match fs::rename(&path, &corrupt).await {
+                    Ok(()) => warn!(
+                        "{} is unreadable ({}); starting with empty runtime state, \
+                         keeping the old file as {}",
+                        path.display(),
+                        e,
+                        corrupt.display()
+                    ),
+                    Err(rename_error) => warn!(
+                        "{} is unreadable ({}); starting with empty runtime state \
+                         (could not keep a copy: {})",
+                        path.display(),
+                        e,
+                        rename_error
+                    ),
+                }
+                Ok(State::default())
+            }
+        }
+    }
+
+    async fn save_state(&self, state: &State) -> Result<()> {
+        fs::create_dir_all(&self.state_dir).await?;
+
+        let content = serde_json::to_string_pretty(state)?;
+        storage::write_atomic(&self.state_path(), content.as_bytes()).await
+    }
+
+    async fn load_history(&self, slug: &str) -> Result<History> {
+        let path = self.history_path(slug);
+
+        if !path.exists() {
+            return Ok(History::default());
+        }
+
+        let content = fs::read_to_string(&path).await?;
+        let history: History = serde_json::from_str(&content)?;
+        Ok(history)
+    }
+
+    async fn save_history(&self, slug: &str, history: &History) -> Result<()> {
+        let path = self.history_path(slug);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let content = serde_json::to_string_pretty(history)?;
+        storage::write_atomic(&path, content.as_bytes()).await
+    }
+
+    async fn record_run(&self, mut run: RunRecord, limit: usize) -> Result<RunRecord> {
+        let _lock = self.lock_history().await?;
+
+        let mut history = self.load_history(&run.slug).await?;
+        run.id = history.next_id();
+        history.runs.push(run.clone());
+        history.prune(limit);
+        self.save_history(&run.slug, &history).await?;
+        Ok(run)
+    }
+
+    async fn update_run(&self, run: &RunRecord) -> Result<()> {
+        let _lock = self.lock_history().await?;
+
+        let mut history = self.load_history(&run.slug).await?;
+        match history.runs.iter_mut().find(|r| r.id == run.id) {
+            Some(existing) => *existing = run.clone(),
+            None => {
+                // Pruned while it was running; keep the final record anyway
+                history.runs.push(run.clone());
+            }
+        }
+        self.save_history(&run.slug, &history).await
+    }
+
+    async fn remove_history(&self, slug: &str) -> Result<()> {
+        let _lock = self.lock_history().await?;
+        let path = self.history_path(slug);
+        if path.exists() {
+            fs::remove_file(&path).await?;
+        }
+        Ok(())
+    }
+}
+
+/// The task list as stored on disk, tagged with the current schema version.
+fn document(events: &[Event]) -> Document<'_> {
+    Document {
+        version: migrations::SCHEMA_VERSION,
+        events,
+    }
+}
+
+async fn read(path: &Path) -> Result<Value> {
+    let content = fs::read_to_string(path).await?;
+    Ok(serde_json::from_str(&content)?)
+}
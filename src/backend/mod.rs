@@ -0,0 +1,255 @@
+use anyhow::Result;
+use nix::fcntl::{Flock, FlockArg};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::history::{History, RunRecord};
+use crate::paths::Dirs;
+use crate::state::State;
+use crate::storage::Storage;
+
+mod json;
+mod sqlite;
+
+pub use json::JsonBackend;
+pub use sqlite::SqliteBackend;
+
+/// Guard for the cross-process storage lock; released when dropped.
+pub type Lock = Flock<std::fs::File>;
+
+/// Where tasks, their runtime state and their run history are persisted.
+///
+/// Writers take [`StorageBackend::lock`] around every read-modify-write cycle
+/// so the CLI, the TUI and the daemon never overwrite each other's changes.
+pub trait StorageBackend {
+    /// Bring stored data written by an older release up to the current schema,
+    /// refusing data from a newer one. Takes the lock itself when needed.
+    fn upgrade(&self) -> impl Future<Output = Result<()>> + Send;
+
+    /// Take the advisory lock serializing writers across processes.
+    fn lock(&self) -> impl Future<Output = Result<Lock>> + Send;
+
+    /// Task definitions, without their runtime state.
+    ///
+    /// Call it while holding [`StorageBackend::lock`]: definitions an older
+    /// release has written since the last upgrade are upgraded and saved.
+    fn load_tasks(&self) -> impl Future<Output = Result<Storage>> + Send;
+
+    fn save_tasks(&self, storage: &Storage) -> impl Future<Output = Result<()>> + Send;
+
+    fn load_state(&self) -> impl Future<Output = Result<State>> + Send;
+
+    fn save_state(&self, state: &State) -> impl Future<Output = Result<()>> + Send;
+
+    /// Run records of one task, oldest first.
+    fn load_history(&self, slug: &str) -> impl Future<Output = Result<History>> + Send;
+
+    /// Replace all run records of one task.
+    fn save_history(
+        &self,
+        slug: &str,
+        history: &History,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Append a run, assigning its id and keeping at most `limit` records.
+    fn record_run(
+        &self,
+        run: RunRecord,
+        limit: usize,
+    ) -> impl Future<Output = Result<RunRecord>> + Send;
+
+    /// Replace a previously recorded run (matched by id) with its final state.
+    fn update_run(&self, run: &RunRecord) -> impl Future<Output = Result<()>> + Send;
+
+    fn remove_history(&self, slug: &str) -> impl Future<Output = Result<()>> + Send;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    /// JSON files
+    #[default]
+    Json,
+    /// An embedded SQLite database
+    Sqlite,
+}
+
+impl std::fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            BackendKind::Json => "json",
+            BackendKind::Sqlite => "sqlite",
+        };
+        f.pad(name)
+    }
+}
+
+/// One of the available backends, picked at runtime.
+#[derive(Debug, Clone)]
+pub enum Backend {
+    Json(JsonBackend),
+    Sqlite(SqliteBackend),
+}
+
+impl Backend {
+    /// The backend selected by `storage_backend` in `config.json`, with its
+    /// data upgraded to the current schema.
+    pub async fn open() -> Result<Self> {
+        Self::open_in(&Dirs::resolve()?).await
+    }
+
+    /// Like [`Backend::open`], for the profile living in `dirs`.
+    pub async fn open_in(dirs: &Dirs) -> Result<Self> {
+        let config = Config::load_from(&dirs.config).await?;
+        let backend = Self::new_in(config.storage_backend, dirs);
+        backend.upgrade().await?;
+        Ok(backend)
+    }
+
+    /// A backend of the given kind for the selected profile.
+    pub fn new(kind: BackendKind) -> Result<Self> {
+        Ok(Self::new_in(kind, &Dirs::resolve()?))
+    }
+
+    fn new_in(kind: BackendKind, dirs: &Dirs) -> Self {
+        match kind {
+            BackendKind::Json => {
+                Backend::Json(JsonBackend::new(dirs.config.clone(), dirs.state.clone()))
+            }
+            BackendKind::Sqlite => {
+                Backend::Sqlite(SqliteBackend::new(dirs.state.join("singleschedule.db")))
+            }
+        }
+    }
+}
+
+impl StorageBackend for Backend {
+    async fn upgrade(&self) -> Result<()> {
+        match self {
+            Backend::Json(backend) => backend.upgrade().await,
+            Backend::Sqlite(backend) => backend.upgrade().await,
+        }
+    }
+
+    async fn lock(&self) -> Result<Lock> {
+        match self {
+            Backend::Json(backend) => backend.lock().await,
+            Backend::Sqlite(backend) => backend.lock().await,
+        }
+    }
+
+    async fn load_tasks(&self) -> Result<Storage> {
+        match self {
+            Backend::Json(backend) => backend.load_tasks().await,
+            Backend::Sqlite(backend) => backend.load_tasks().await,
+        }
+    }
+
+    async fn save_tasks(&self, storage: &Storage) -> Result<()> {
+        match self {
+            Backend::Json(backend) => backend.save_tasks(storage).await,
+            Backend::Sqlite(backend) => backend.save_tasks(storage).await,
+        }
+    }
+
+    async fn load_state(&self) -> Result<State> {
+        match self {
+            Backend::Json(backend) => backend.load_state().await,
+            Backend::Sqlite(backend) => backend.load_state().await,
+        }
+    }
+
+    async fn save_state(&self, state: &State) -> Result<()> {
+        match self {
+            Backend::Json(backend) => backend.save_state(state).await,
+            Backend::Sqlite(backend) => backend.save_state(state).await,
+        }
+    }
+
+    async fn load_history(&self, slug: &str) -> Result<History> {
+        match self {
+            Backend::Json(backend) => backend.load_history(slug).await,
+            Backend::Sqlite(backend) => backend.load_history(slug).await,
+        }
+    }
+
+    async fn save_history(&self, slug: &str, history: &History) -> Result<()> {
+        match self {
+            Backend::Json(backend) => backend.save_history(slug, history).await,
+            Backend::Sqlite(backend) => backend.save_history(slug, history).await,
+        }
+    }
+
+    async fn record_run(&self, run: RunRecord, limit: usize) -> Result<RunRecord> {
+        match self {
+            Backend::Json(backend) => backend.record_run(run, limit).await,
+            Backend::Sqlite(backend) => backend.record_run(run, limit).await,
+        }
+    }
+
+    async fn update_run(&self, run: &RunRecord) -> Result<()> {
+        match self {
+            Backend::Json(backend) => backend.update_run(run).await,
+            Backend::Sqlite(backend) => backend.update_run(run).await,
+        }
+    }
+
+    async fn remove_history(&self, slug: &str) -> Result<()> {
+        match self {
+            Backend::Json(backend) => backend.remove_history(slug).await,
+            Backend::Sqlite(backend) => backend.remove_history(slug).await,
+        }
+    }
+}
+
+/// Copy every task with its runtime state and run history from one backend
+/// to another, returning the number of tasks copied.
+///
+/// A target that already has tasks is left alone rather than merged into.
+pub async fn copy_storage(from: &impl StorageBackend, to: &impl StorageBackend) -> Result<usize> {
+    from.upgrade().await?;
+    to.upgrade().await?;
+    let _from_lock = from.lock().await?;
+    let _to_lock = to.lock().await?;
+
+    if !to.load_tasks().await?.events.is_empty() {
+        return Err(anyhow::anyhow!(
+            "The target storage already has tasks; refusing to overwrite them"
+        ));
+    }
+
+    let storage = from.load_tasks().await?;
+    for event in &storage.events {
+        let history = from.load_history(&event.slug).await?;
+        to.save_history(&event.slug, &history).await?;
+    }
+    to.save_state(&from.load_state().await?).await?;
+    // Tasks go last, so an interrupted copy leaves the target empty and can
+    // simply be run again
+    to.save_tasks(&storage).await?;
+
+    Ok(storage.events.len())
+}
+
+/// Take an exclusive `flock` on `path`, creating the file if needed.
+///
+/// The lock lives on a file of its own because data files may be replaced
+/// by rename while it is held.
+async fn lock_file(path: PathBuf) -> Result<Lock> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    tokio::task::spawn_blocking(move || {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+        Flock::lock(file, FlockArg::LockExclusive)
+            .map_err(|(_, e)| anyhow::anyhow!("Failed to lock {}: {}", path.display(), e))
+    })
+    .await?
+}
@@ -0,0 +1,259 @@
+use anyhow::Result;
+use rusqlite::{params, Connection, TransactionBehavior};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::{Lock, StorageBackend};
+use crate::history::{History, RunRecord};
+use crate::migrations;
+use crate::state::State;
+use crate::storage::Storage;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS tasks (
+        slug TEXT PRIMARY KEY,
+        position INTEGER NOT NULL,
+        definition TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS task_state (
+        slug TEXT PRIMARY KEY,
+        state TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS runs (
+        slug TEXT NOT NULL,
+        id INTEGER NOT NULL,
+        record TEXT NOT NULL,
+        PRIMARY KEY (slug, id)
+    );
+";
+
+/// Tasks, runtime state and run history in one SQLite database.
+///
+/// Rows hold the same JSON the file backend writes, so both share one schema
+/// version (kept in `PRAGMA user_version`); only lookup keys are columns.
+/// Run records are appended and pruned in place instead of rewriting a
+/// task's whole history on every run.
+#[derive(Debug, Clone)]
+pub struct SqliteBackend {
+    path: PathBuf,
+}
+
+impl SqliteBackend {
+    pub fn new(path: PathBuf) -> Self {
+        SqliteBackend { path }
+    }
+
+    /// Run `work` on a fresh connection on the blocking thread pool.
+    async fn with_connection<T: Send + 'static>(
+        &self,
+        work: impl FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = open(&path)?;
+            work(&mut conn)
+        })
+        .await?
+    }
+}
+
+impl StorageBackend for SqliteBackend {
+    async fn upgrade(&self) -> Result<()> {
+        // Opening a connection creates the schema and checks its version
+        self.with_connection(|_| Ok(())).await
+    }
+
+    async fn lock(&self) -> Result<Lock> {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".lock");
+        super::lock_file(path.into()).await
+    }
+
+    async fn load_tasks(&self) -> Result<Storage> {
+        self.with_connection(|conn| {
+            let mut stmt = conn.prepare("SELECT definition FROM tasks ORDER BY position")?;
+            let events = stmt
+                .query_map([], |row| row.get::<_, String>(0))?
+                .map(|definition| Ok(serde_json::from_str(&definition?)?))
+                .collect::<Result<_>>()?;
+            Ok(Storage { events })
+        })
+        .await
+    }
+
+    async fn save_tasks(&self, storage: &Storage) -> Result<()> {
+        let rows = storage
+            .events
+            .iter()
+            .map(|event| Ok((event.slug.clone(), serde_json::to_string(event)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        self.with_connection(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM tasks", [])?;
+            for (position, (slug, definition)) in rows.iter().enumerate() {
+                tx.execute(
+                    "INSERT INTO tasks (slug, position, definition) VALUES (?1, ?2, ?3)",
+                    params![slug, position as i64, definition],
+                )?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn load_state(&self) -> Result<State> {
+        self.with_connection(|conn| {
+            let mut stmt = conn.prepare("SELECT slug, state FROM task_state")?;
+            let tasks = stmt
+                .query_map([], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })?
+                .map(|row| {
+                    let (slug, state) = row?;
+                    Ok((slug, serde_json::from_str(&state)?))
+                })
+                .collect::<Result<BTreeMap<_, _>>>()?;
+            Ok(State { tasks })
+        })
+        .await
+    }
+
+    async fn save_state(&self, state: &State) -> Result<()> {
+        let rows = state
+            .tasks
+            .iter()
+            .map(|(slug, task)| Ok((slug.clone(), serde_json::to_string(task)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        self.with_connection(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM task_state", [])?;
+            for (slug, state) in &rows {
+                tx.execute(
+                    "INSERT INTO task_state (slug, state) VALUES (?1, ?2)",
+                    params![slug, state],
+                )?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn load_history(&self, slug: &str) -> Result<History> {
+        let slug = slug.to_string();
+        self.with_connection(move |conn| {
+            let mut stmt = conn.prepare("SELECT record FROM runs WHERE slug = ?1 ORDER BY id")?;
+            let runs = stmt
+                .query_map([&slug], |row| row.get::<_, String>(0))?
+                .map(|record| Ok(serde_json::from_str(&record?)?))
+                .collect::<Result<_>>()?;
+            Ok(History { runs })
+        })
+        .await
+    }
+
+    async fn save_history(&self, slug: &str, history: &History) -> Result<()> {
+        let slug = slug.to_string();
+        let rows = history
+            .runs
+            .iter()
+            .map(|run| Ok((run.id as i64, serde_json::to_string(run)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        self.with_connection(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM runs WHERE slug = ?1", [&slug])?;
+            for (id, record) in &rows {
+                tx.execute(
+                    "INSERT INTO runs (slug, id, record) VALUES (?1, ?2, ?3)",
+                    params![slug, id, record],
+                )?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn record_run(&self, mut run: RunRecord, limit: usize) -> Result<RunRecord> {
+        self.with_connection(move |conn| {
+            // Take the write lock up front so two writers never pick the same id
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let id: i64 = tx.query_row(
+                "SELECT COALESCE(MAX(id), 0) + 1 FROM runs WHERE slug = ?1",
+                [&run.slug],
+                |row| row.get(0),
+            )?;
+            run.id = id as u64;
+
+            tx.execute(
+                "INSERT INTO runs (slug, id, record) VALUES (?1, ?2, ?3)",
+                params![run.slug, id, serde_json::to_string(&run)?],
+            )?;
+            tx.execute(
+                "DELETE FROM runs WHERE slug = ?1 AND id NOT IN
+                    (SELECT id FROM runs WHERE slug = ?1 ORDER BY id DESC LIMIT ?2)",
+                params![run.slug, limit as i64],
+            )?;
+            tx.commit()?;
+            Ok(run)
+        })
+        .await
+    }
+
+    async fn update_run(&self, run: &RunRecord) -> Result<()> {
+        let slug = run.slug.clone();
+        let id = run.id as i64;
+        let record = serde_json::to_string(run)?;
+
+        self.with_connection(move |conn| {
+            // Inserts the final record even if it was pruned while running
+            conn.execute(
+                "INSERT INTO runs (slug, id, record) VALUES (?1, ?2, ?3)
+                    ON CONFLICT (slug, id) DO UPDATE SET record = excluded.record",
+                params![slug, id, record],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn remove_history(&self, slug: &str) -> Result<()> {
+        let slug = slug.to_string();
+        self.with_connection(move |conn| {
+            conn.execute("DELETE FROM runs WHERE slug = ?1", [&slug])?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// Open the database, creating its tables on first use.
+fn open(path: &Path) -> Result<Connection> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let conn = Connection::open(path)?;
+    // The CLI and the daemon share the file; wait for each other's writes
+    // rather than failing with SQLITE_BUSY
+    conn.busy_timeout(Duration::from_secs(5))?;
+    conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+
+    let version: u64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    migrations::ensure_supported(&path.display().to_string(), version)?;
+
+    conn.execute_batch(SCHEMA)?;
+    if version == 0 {
+        // New databases start out at the current layout; future layout
+        // changes add upgrade steps here, like `migrations` does for
+        // events.json
+        conn.pragma_update(None, "user_version", migrations::SCHEMA_VERSION)?;
+    }
+
+    Ok(conn)
+}
@@ -1,12 +1,30 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use std::str::FromStr;
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-use crate::storage::{Event, Storage};
+use crate::backend::{self, Backend, BackendKind};
+use crate::config::Config;
+use crate::control::{self, Reply, Request};
+use crate::crontab::{self, Import};
+use crate::export::{self, Format};
+use crate::history::{History, RunRecord, RunStatus, MAX_OUTPUT_BYTES};
+use crate::paths::{self, Dirs};
+use crate::schedule::{self, TaskSchedule};
+use crate::storage::{ConcurrencyPolicy, Event, MisfirePolicy, Storage, DEFAULT_SHELL};
+use crate::taskfile::{Change, Plan, TaskFile};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
+    /// Keep all task definitions, state and logs in DIR (also set by $SINGLESCHEDULE_HOME)
+    #[arg(long, global = true, value_name = "DIR")]
+    pub data_dir: Option<PathBuf>,
+
+    /// Work with the tasks and daemon of this profile (also set by $SINGLESCHEDULE_PROFILE)
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -23,6 +41,38 @@ pub enum Commands {
         #[arg(short, long)]
         cron: String,
 
+        /// Run the command through a shell (`<shell> -c`), defaulting to /bin/sh
+        #[arg(long, value_name = "SHELL", num_args = 0..=1, default_missing_value = DEFAULT_SHELL)]
+        shell: Option<String>,
+
+        /// Number of past runs to keep in the task's history
+        #[arg(long, value_name = "N")]
+        history_limit: Option<usize>,
+
+        /// Kill the run if it takes longer than this (e.g. 90, 30s, 5m, 2h)
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        timeout: Option<u64>,
+
+        /// What to do when the task fires while its previous run is still going
+        #[arg(long, value_enum, default_value_t = ConcurrencyPolicy::Skip)]
+        concurrency_policy: ConcurrencyPolicy,
+
+        /// What to do about fire times missed while the daemon was down
+        #[arg(long, value_enum, default_value_t = MisfirePolicy::RunOnce)]
+        misfire_policy: MisfirePolicy,
+
+        /// Most missed runs to catch up with `--misfire-policy run-all-missed`
+        #[arg(long, value_name = "N")]
+        max_missed_runs: Option<usize>,
+
+        /// How late a run may start and still count as on time (e.g. 30s, 5m)
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        misfire_grace: Option<u64>,
+
+        /// IANA timezone to evaluate the cron expression in (e.g. Europe/Berlin)
+        #[arg(long, value_name = "TZ")]
+        timezone: Option<String>,
+
         /// Command to execute (everything after --)
         #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         command: Vec<String>,
@@ -36,7 +86,11 @@ pub enum Commands {
     },
 
     /// List all scheduled tasks
-    List,
+    List {
+        /// List the tasks of every profile, not just the selected one
+        #[arg(long)]
+        all_profiles: bool,
+    },
 
     /// Start the scheduler daemon
     Start {
@@ -60,174 +114,463 @@ pub enum Commands {
         all: bool,
     },
 
+    /// Show whether the daemon is running and what it is doing
+    Status,
+
+    /// Run a task now, outside its schedule (needs the daemon)
+    Trigger {
+        /// Slug of the task to run
+        slug: String,
+    },
+
+    /// Run the scheduler without changing which tasks are active
+    Run {
+        /// Stay in the foreground (no fork, logs on stderr) for systemd,
+        /// supervisord or a container
+        #[arg(long)]
+        foreground: bool,
+
+        /// Write a PID file in the foreground; without PATH, the daemon's own,
+        /// so that other commands reload this scheduler instead of starting one
+        #[arg(long, value_name = "PATH", num_args = 0..=1, requires = "foreground")]
+        pid_file: Option<Option<PathBuf>>,
+
+        /// Fork into the daemon right away; how `start` launches it
+        #[arg(long, hide = true, conflicts_with = "foreground")]
+        daemonize: bool,
+    },
+
     /// Launch the interactive TUI
     Tui,
+
+    /// Show past runs of a task
+    History {
+        /// Slug of the task
+        slug: String,
+
+        /// Maximum number of runs to show (most recent first)
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Print the captured output of a task run
+    Logs {
+        /// Slug of the task
+        slug: String,
+
+        /// Run id to show (defaults to the latest run)
+        #[arg(short, long, value_name = "ID")]
+        run: Option<u64>,
+
+        /// Keep streaming output while the run is still in progress
+        #[arg(short, long)]
+        follow: bool,
+    },
+
+    /// Copy all tasks, runtime state and run history to another storage backend
+    MigrateStorage {
+        /// Backend to copy to
+        #[arg(long, value_enum)]
+        to: BackendKind,
+
+        /// Backend to copy from (defaults to the one selected in config.json)
+        #[arg(long, value_enum)]
+        from: Option<BackendKind>,
+    },
+
+    /// Bring the stored tasks in line with a TOML task file
+    Apply {
+        /// Task file to apply
+        file: PathBuf,
+
+        /// Also remove tasks that are not in the file
+        #[arg(long)]
+        prune: bool,
+    },
+
+    /// Show what `apply` would change, without changing anything
+    Diff {
+        /// Task file to compare against
+        file: PathBuf,
+
+        /// Include tasks that `apply --prune` would remove
+        #[arg(long)]
+        prune: bool,
+    },
+
+    /// Write tasks out as a task file, systemd user units or a crontab
+    Export {
+        /// Tasks to export (defaults to all)
+        slugs: Vec<String>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = Format::Toml)]
+        format: Format,
+
+        /// File to write, or directory for systemd units (defaults to stdout)
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+
+    /// Create tasks from a crontab
+    ImportCrontab {
+        /// Crontab file to read, or - for stdin (defaults to `crontab -l`)
+        #[arg(value_name = "FILE|-")]
+        file: Option<PathBuf>,
+
+        /// Show what would be imported without creating any tasks
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
-pub async fn handle_add(slug: String, cron_expr: String, command: Vec<String>) -> Result<()> {
-    // Validate cron expression
-    let _schedule = cron::Schedule::from_str(&cron_expr)
-        .map_err(|e| anyhow::anyhow!("Invalid cron expression: {}", e))?;
+/// Optional per-task settings accepted by `add`.
+#[derive(Debug, Clone, Default)]
+pub struct AddOptions {
+    /// Run the command through this shell instead of executing it directly.
+    pub shell: Option<String>,
+    /// Number of run records kept for the task.
+    pub history_limit: Option<usize>,
+    /// Seconds before a run is terminated.
+    pub timeout: Option<u64>,
+    /// Behaviour when the task fires while a previous run is still going.
+    pub concurrency_policy: ConcurrencyPolicy,
+    /// Behaviour for fire times that were missed.
+    pub misfire_policy: MisfirePolicy,
+    /// Cap on runs caught up under `run_all_missed`.
+    pub max_missed_runs: Option<usize>,
+    /// Seconds a fire time may be late and still run as scheduled.
+    pub misfire_grace: Option<u64>,
+    /// IANA timezone the schedule is evaluated in.
+    pub timezone: Option<String>,
+}
 
-    let mut storage = Storage::load().await?;
+/// Parse a duration such as `90`, `30s`, `5m` or `2h` into seconds.
+pub fn parse_duration(input: &str) -> std::result::Result<u64, String> {
+    let input = input.trim();
+    let (digits, multiplier) = match input.char_indices().last() {
+        Some((idx, 's')) => (&input[..idx], 1),
+        Some((idx, 'm')) => (&input[..idx], 60),
+        Some((idx, 'h')) => (&input[..idx], 3600),
+        Some((idx, 'd')) => (&input[..idx], 86400),
+        _ => (input, 1),
+    };
+
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid duration '{input}' (expected e.g. 90, 30s, 5m, 2h)"))?;
 
-    // Check if slug already exists
-    if storage.events.iter().any(|e| e.slug == slug) {
-        return Err(anyhow::anyhow!("Task with slug '{}' already exists", slug));
+    if value == 0 {
+        return Err("duration must be greater than zero".to_string());
     }
 
+    value
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("duration '{input}' is too long"))
+}
+
+pub async fn handle_add(
+    slug: String,
+    cron_expr: String,
+    command: Vec<String>,
+    options: AddOptions,
+) -> Result<()> {
+    // Validate cron expression and timezone
+    TaskSchedule::new(&cron_expr, options.timezone.as_deref())?;
+
+    if command.is_empty() {
+        return Err(anyhow::anyhow!("Command cannot be empty"));
+    }
+
+    // In shell mode the words are handed to the shell as one script; otherwise
+    // keep the exact tokens so quoting survives.
+    let argv = if options.shell.is_some() {
+        None
+    } else {
+        Some(command.clone())
+    };
+
     let event = Event {
         slug: slug.clone(),
         cron: cron_expr,
         command: command.join(" "),
-        pid: None,
-        created_at: chrono::Utc::now(),
-        last_run: None,
-        active: true,
+        argv,
+        shell: options.shell,
+        history_limit: options.history_limit,
+        timeout: options.timeout,
+        concurrency_policy: options.concurrency_policy,
+        misfire_policy: options.misfire_policy,
+        max_missed_runs: options.max_missed_runs,
+        misfire_grace: options.misfire_grace,
+        timezone: options.timezone,
+        ..Default::default()
     };
 
-    storage.events.push(event);
-    storage.save().await?;
+    Storage::update(|storage| storage.insert_event(event)).await?;
 
     println!("Task '{slug}' added successfully");
 
-    // Restart daemon to pick up new task
-    if let Err(e) = crate::daemon::restart_daemon().await {
-        eprintln!("Warning: Failed to restart daemon: {e}");
-        eprintln!("Please restart the daemon manually with 'singleschedule start'");
+    // Have the daemon pick up the new task
+    if let Err(e) = crate::daemon::reload_daemon().await {
+        eprintln!("Warning: Failed to reload daemon: {e}");
+        eprintln!("Please start the daemon manually with 'singleschedule start'");
     }
 
     Ok(())
 }
 
 pub async fn handle_remove(slug: String) -> Result<()> {
-    let mut storage = Storage::load().await?;
+    Storage::update(|storage| {
+        let initial_count = storage.events.len();
+        storage.events.retain(|e| e.slug != slug);
 
-    let initial_count = storage.events.len();
-    storage.events.retain(|e| e.slug != slug);
+        if storage.events.len() == initial_count {
+            return Err(anyhow::anyhow!("Task with slug '{}' not found", slug));
+        }
+        Ok(())
+    })
+    .await?;
 
-    if storage.events.len() == initial_count {
-        return Err(anyhow::anyhow!("Task with slug '{}' not found", slug));
+    if let Err(e) = crate::history::History::remove(&slug).await {
+        eprintln!("Warning: Failed to remove run history: {e}");
     }
 
-    storage.save().await?;
     println!("Task '{slug}' removed successfully");
 
-    // Restart daemon to update tasks
-    if let Err(e) = crate::daemon::restart_daemon().await {
-        eprintln!("Warning: Failed to restart daemon: {e}");
-        eprintln!("Please restart the daemon manually with 'singleschedule start'");
+    // Have the daemon pick up the changes
+    if let Err(e) = crate::daemon::reload_daemon().await {
+        eprintln!("Warning: Failed to reload daemon: {e}");
+        eprintln!("Please start the daemon manually with 'singleschedule start'");
     }
 
     Ok(())
 }
 
-pub async fn handle_list() -> Result<()> {
-    let storage = Storage::load().await?;
+/// A line of `list` output.
+struct ListRow {
+    profile: String,
+    slug: String,
+    cron: String,
+    command: String,
+    status: &'static str,
+    last_run: Option<chrono::DateTime<chrono::Utc>>,
+    next_run: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+pub async fn handle_list(all_profiles: bool) -> Result<()> {
+    // A running daemon knows which tasks are running right now
+    if !all_profiles {
+        if let Some(reply) = control::request(&Request::List).await? {
+            let Reply::Tasks(tasks) = reply else {
+                return Err(control::unexpected(reply));
+            };
+            let profile = paths::current_profile()?;
+            let rows = tasks
+                .into_iter()
+                .map(|task| ListRow {
+                    profile: profile.clone(),
+                    status: match (task.pids.is_empty(), task.active) {
+                        (false, _) => "Running",
+                        (true, true) => "Active",
+                        (true, false) => "Inactive",
+                    },
+                    slug: task.slug,
+                    cron: task.cron,
+                    command: task.command,
+                    last_run: task.last_run,
+                    next_run: task.next_run,
+                })
+                .collect();
+            print_list(rows, false);
+            return Ok(());
+        }
+    }
+
+    let tasks: Vec<(String, Event)> = if all_profiles {
+        let base = Dirs::base()?;
+        let mut tasks = Vec::new();
+        for profile in base.profiles()? {
+            let backend = Backend::open_in(&base.clone().profile(&profile)).await?;
+            let storage = Storage::load_from(&backend).await?;
+            tasks.extend(storage.events.into_iter().map(|e| (profile.clone(), e)));
+        }
+        tasks
+    } else {
+        let profile = paths::current_profile()?;
+        let storage = Storage::load().await?;
+        storage
+            .events
+            .into_iter()
+            .map(|e| (profile.clone(), e))
+            .collect()
+    };
 
-    if storage.events.is_empty() {
+    let now = chrono::Utc::now();
+    let rows = tasks
+        .into_iter()
+        .map(|(profile, event)| ListRow {
+            profile,
+            status: if event.active { "Active" } else { "Inactive" },
+            next_run: schedule::next_run(&event, now),
+            last_run: event.last_run,
+            slug: event.slug,
+            cron: event.cron,
+            command: event.command,
+        })
+        .collect();
+    print_list(rows, all_profiles);
+    Ok(())
+}
+
+fn print_list(rows: Vec<ListRow>, all_profiles: bool) {
+    if rows.is_empty() {
         println!("No scheduled tasks");
-        return Ok(());
+        return;
     }
 
+    // The profile column only shows up when listing more than one
+    let profile_column = |profile: &str| {
+        if all_profiles {
+            format!("{profile:<16} ")
+        } else {
+            String::new()
+        }
+    };
+
     println!(
-        "{:<20} {:<20} {:<40} {:<10} {:<15}",
-        "SLUG", "CRON", "COMMAND", "STATUS", "LAST RUN"
+        "{}{:<20} {:<20} {:<40} {:<10} {:<17} {:<17}",
+        profile_column("PROFILE"),
+        "SLUG",
+        "CRON",
+        "COMMAND",
+        "STATUS",
+        "LAST RUN",
+        "NEXT RUN"
     );
-    println!("{}", "-".repeat(105));
+    println!("{}", "-".repeat(if all_profiles { 146 } else { 129 }));
 
-    for event in &storage.events {
-        let last_run = event
+    for row in &rows {
+        let last_run = row
             .last_run
-            .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
+            .map(schedule::format_local)
             .unwrap_or_else(|| "Never".to_string());
+        let next_run = row
+            .next_run
+            .map(schedule::format_local)
+            .unwrap_or_else(|| "-".to_string());
 
-        let command = if event.command.len() > 37 {
-            format!("{}...", &event.command[..37])
+        let command = if row.command.chars().count() > 37 {
+            format!("{}...", row.command.chars().take(37).collect::<String>())
         } else {
-            event.command.clone()
+            row.command.clone()
         };
 
-        let status = if event.active { "Active" } else { "Inactive" };
-
         println!(
-            "{:<20} {:<20} {:<40} {:<10} {:<15}",
-            event.slug, event.cron, command, status, last_run
+            "{}{:<20} {:<20} {:<40} {:<10} {:<17} {:<17}",
+            profile_column(&row.profile),
+            row.slug,
+            row.cron,
+            command,
+            row.status,
+            last_run,
+            next_run
         );
     }
-
-    Ok(())
 }
 
 pub async fn handle_start(slugs: Vec<String>, all: bool) -> Result<()> {
-    let mut storage = Storage::load().await?;
+    // A running daemon makes the change itself
+    let resume = Request::Resume {
+        slugs: slugs.clone(),
+    };
+    if let Some(reply) = control::request(&resume).await? {
+        let (found_count, changed_count) = updated_counts(reply)?;
+        if !slugs.is_empty() {
+            if found_count == 0 {
+                return Err(anyhow::anyhow!("No valid tasks found to start"));
+            }
+            println!("Started {found_count} task(s)");
+        } else if changed_count > 0 {
+            println!("Started all {changed_count} inactive task(s)");
+        } else {
+            println!("All tasks are already active");
+        }
+        return Ok(());
+    }
 
     if !slugs.is_empty() {
         // Start specific tasks
-        let mut found_count = 0;
-        for slug in &slugs {
-            if let Some(event) = storage.events.iter_mut().find(|e| &e.slug == slug) {
-                event.active = true;
-                found_count += 1;
-            } else {
-                eprintln!("Warning: Task with slug '{slug}' not found");
+        let found_count = Storage::update(|storage| {
+            let found_count = set_active(storage, &slugs, true);
+            if found_count == 0 {
+                return Err(anyhow::anyhow!("No valid tasks found to start"));
             }
-        }
-
-        if found_count == 0 {
-            return Err(anyhow::anyhow!("No valid tasks found to start"));
-        }
+            Ok(found_count)
+        })
+        .await?;
 
-        storage.save().await?;
         println!("Started {found_count} task(s)");
     } else if all || slugs.is_empty() {
         // Start all tasks (explicit --all or no arguments)
-        let inactive_count = storage.events.iter_mut().filter(|e| !e.active).count();
-        for event in &mut storage.events {
-            event.active = true;
-        }
+        let inactive_count = Storage::update(|storage| {
+            let inactive_count = storage.events.iter_mut().filter(|e| !e.active).count();
+            for event in &mut storage.events {
+                event.active = true;
+            }
+            Ok(inactive_count)
+        })
+        .await?;
 
         if inactive_count > 0 {
-            storage.save().await?;
             println!("Started all {inactive_count} inactive task(s)");
         } else {
             println!("All tasks are already active");
         }
     }
 
-    // Start or restart the daemon
-    crate::daemon::start_daemon().await?;
+    // Start the daemon, or have a running one pick up the started tasks
+    crate::daemon::reload_daemon().await?;
 
     Ok(())
 }
 
 pub async fn handle_stop(slugs: Vec<String>, all: bool) -> Result<()> {
-    let mut storage = Storage::load().await?;
-
     if !slugs.is_empty() {
-        // Stop specific tasks
-        let mut found_count = 0;
-        for slug in &slugs {
-            if let Some(event) = storage.events.iter_mut().find(|e| &e.slug == slug) {
-                event.active = false;
-                found_count += 1;
-            } else {
-                eprintln!("Warning: Task with slug '{slug}' not found");
+        // A running daemon makes the change itself
+        let pause = Request::Pause {
+            slugs: slugs.clone(),
+        };
+        if let Some(reply) = control::request(&pause).await? {
+            let (found_count, _) = updated_counts(reply)?;
+            if found_count == 0 {
+                return Err(anyhow::anyhow!("No valid tasks found to stop"));
             }
-        }
+            println!("Stopped {found_count} task(s)");
 
-        if found_count == 0 {
-            return Err(anyhow::anyhow!("No valid tasks found to stop"));
+            // No active tasks, stop daemon
+            if !Storage::load().await?.events.iter().any(|e| e.active) {
+                crate::daemon::stop_daemon().await?;
+            }
+            return Ok(());
         }
 
-        storage.save().await?;
+        // Stop specific tasks
+        let (found_count, any_active) = Storage::update(|storage| {
+            let found_count = set_active(storage, &slugs, false);
+            if found_count == 0 {
+                return Err(anyhow::anyhow!("No valid tasks found to stop"));
+            }
+            Ok((found_count, storage.events.iter().any(|e| e.active)))
+        })
+        .await?;
+
         println!("Stopped {found_count} task(s)");
 
         // Check if any tasks are still active
-        if storage.events.iter().any(|e| e.active) {
-            // Some tasks still active, restart daemon
-            if let Err(e) = crate::daemon::restart_daemon().await {
-                eprintln!("Warning: Failed to restart daemon: {e}");
+        if any_active {
+            // Some tasks still active, reload daemon
+            if let Err(e) = crate::daemon::reload_daemon().await {
+                eprintln!("Warning: Failed to reload daemon: {e}");
             }
         } else {
             // No active tasks, stop daemon
@@ -241,8 +584,551 @@ pub async fn handle_stop(slugs: Vec<String>, all: bool) -> Result<()> {
     Ok(())
 }
 
+pub async fn handle_run(
+    foreground: bool,
+    pid_file: Option<Option<PathBuf>>,
+    notifier: Option<crate::sd_notify::Notifier>,
+) -> Result<()> {
+    if !foreground {
+        return crate::daemon::start_daemon().await;
+    }
+
+    let pid_file = match pid_file {
+        Some(Some(path)) => Some(path),
+        Some(None) => Some(crate::daemon::get_pid_file()?),
+        None => None,
+    };
+    crate::daemon::run_foreground(pid_file, notifier).await
+}
+
+pub async fn handle_status() -> Result<()> {
+    let status = match control::request(&Request::Status).await? {
+        Some(Reply::Status(status)) => status,
+        Some(reply) => return Err(control::unexpected(reply)),
+        None => return Err(anyhow::anyhow!("Daemon is not running")),
+    };
+
+    println!(
+        "Daemon running with PID {} since {}",
+        status.pid,
+        schedule::format_local(status.started_at)
+    );
+    println!(
+        "Tasks:    {} ({} active)",
+        status.tasks, status.active_tasks
+    );
+    println!("Running:  {}", status.running);
+    println!("Queued:   {}", status.queued);
+    println!(
+        "Next run: {}",
+        status
+            .next_fire
+            .map(schedule::format_local)
+            .unwrap_or_else(|| "-".to_string())
+    );
+    Ok(())
+}
+
+pub async fn handle_trigger(slug: String) -> Result<()> {
+    let trigger = Request::Trigger { slug: slug.clone() };
+    match control::request(&trigger).await? {
+        Some(Reply::Done) => {
+            println!("Triggered task '{slug}'");
+            Ok(())
+        }
+        Some(reply) => Err(control::unexpected(reply)),
+        None => Err(anyhow::anyhow!(
+            "Daemon is not running; start it with 'singleschedule start'"
+        )),
+    }
+}
+
+/// Counts of tasks found and changed from a `pause` or `resume` reply,
+/// warning about slugs that matched no task.
+fn updated_counts(reply: Reply) -> Result<(usize, usize)> {
+    let Reply::Updated {
+        found,
+        changed,
+        missing,
+    } = reply
+    else {
+        return Err(control::unexpected(reply));
+    };
+    for slug in missing {
+        eprintln!("Warning: Task with slug '{slug}' not found");
+    }
+    Ok((found, changed))
+}
+
+/// Mark the given tasks active or inactive, returning how many were found.
+fn set_active(storage: &mut Storage, slugs: &[String], active: bool) -> usize {
+    let mut found_count = 0;
+    for slug in slugs {
+        if let Some(event) = storage.events.iter_mut().find(|e| &e.slug == slug) {
+            event.active = active;
+            found_count += 1;
+        } else {
+            eprintln!("Warning: Task with slug '{slug}' not found");
+        }
+    }
+    found_count
+}
+
 pub async fn handle_tui() -> Result<()> {
     crate::tui::run_tui()
         .await
         .map_err(|e| anyhow::anyhow!("TUI error: {}", e))
 }
+
+pub async fn handle_history(slug: String, limit: usize) -> Result<()> {
+    let history = load_history(&slug).await?;
+
+    if history.runs.is_empty() {
+        println!("No runs recorded for task '{slug}'");
+        return Ok(());
+    }
+
+    println!(
+        "{:<6} {:<20} {:<10} {:<10} {:<6}",
+        "RUN", "STARTED", "DURATION", "STATUS", "EXIT"
+    );
+    println!("{}", "-".repeat(56));
+
+    for run in history.runs.iter().rev().take(limit) {
+        let duration = run
+            .duration_ms
+            .map(format_duration_ms)
+            .unwrap_or_else(|| "-".to_string());
+
+        let exit = match (run.exit_code, run.signal) {
+            (Some(code), _) => code.to_string(),
+            (None, Some(signal)) => format!("SIG{signal}"),
+            (None, None) => "-".to_string(),
+        };
+
+        println!(
+            "{:<6} {:<20} {:<10} {:<10} {:<6}",
+            run.id,
+            schedule::format_local(run.started_at),
+            duration,
+            run.status,
+            exit
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn handle_logs(slug: String, run: Option<u64>, follow: bool) -> Result<()> {
+    let history = load_history(&slug).await?;
+
+    let record = match run {
+        Some(id) => history
+            .get(id)
+            .ok_or_else(|| anyhow::anyhow!("Run {} of task '{}' not found", id, slug))?,
+        None => history
+            .latest()
+            .ok_or_else(|| anyhow::anyhow!("No runs recorded for task '{}'", slug))?,
+    };
+
+    if record.status != RunStatus::Running {
+        print_run_output(record);
+        return Ok(());
+    }
+
+    if follow {
+        return follow_run(&slug, record.id).await;
+    }
+
+    // Show what the run has produced so far
+    let path = History::live_log_path(&slug, record.id)?;
+    if let Ok(output) = tokio::fs::read(&path).await {
+        print!("{}", String::from_utf8_lossy(&output));
+    }
+    eprintln!(
+        "Run {} is still in progress (use --follow to stream it)",
+        record.id
+    );
+
+    Ok(())
+}
+
+async fn load_history(slug: &str) -> Result<History> {
+    let history = History::load(slug).await?;
+
+    if history.runs.is_empty() {
+        let storage = Storage::load().await?;
+        if !storage.events.iter().any(|e| e.slug == slug) {
+            return Err(anyhow::anyhow!("Task with slug '{}' not found", slug));
+        }
+    }
+
+    Ok(history)
+}
+
+pub async fn handle_migrate_storage(from: Option<BackendKind>, to: BackendKind) -> Result<()> {
+    let from = match from {
+        Some(from) => from,
+        None => Config::load().await?.storage_backend,
+    };
+    if from == to {
+        return Err(anyhow::anyhow!(
+            "Source and target backend are both '{}'",
+            to
+        ));
+    }
+
+    let count = backend::copy_storage(&Backend::new(from)?, &Backend::new(to)?).await?;
+
+    println!("Copied {count} task(s) from {from} to {to} storage");
+    println!(
+        "Set \"storage_backend\": \"{}\" in {} to start using it",
+        to,
+        paths::config_dir()?.join("config.json").display()
+    );
+
+    Ok(())
+}
+
+pub async fn handle_apply(file: PathBuf, prune: bool) -> Result<()> {
+    let task_file = TaskFile::load(&file).await?;
+
+    let plan = Storage::update(|storage| {
+        let plan = task_file.plan(storage, prune)?;
+        plan.apply(storage);
+        Ok(plan)
+    })
+    .await?;
+
+    print_plan(&plan, &file);
+    if plan.changes.is_empty() {
+        return Ok(());
+    }
+
+    for change in &plan.changes {
+        if let Change::Remove(slug) = change {
+            if let Err(e) = History::remove(slug).await {
+                eprintln!("Warning: Failed to remove run history of '{slug}': {e}");
+            }
+        }
+    }
+
+    let count = |kind: fn(&Change) -> bool| plan.changes.iter().filter(|c| kind(c)).count();
+    println!(
+        "Applied {}: {} added, {} updated, {} removed",
+        file.display(),
+        count(|c| matches!(c, Change::Add(_))),
+        count(|c| matches!(c, Change::Update { .. })),
+        count(|c| matches!(c, Change::Remove(_)))
+    );
+
+    // Have the daemon pick up the changes
+    if let Err(e) = crate::daemon::reload_daemon().await {
+        eprintln!("Warning: Failed to reload daemon: {e}");
+        eprintln!("Please start the daemon manually with 'singleschedule start'");
+    }
+
+    Ok(())
+}
+
+pub async fn handle_diff(file: PathBuf, prune: bool) -> Result<()> {
+    let task_file = TaskFile::load(&file).await?;
+    let storage = Storage::load().await?;
+
+    print_plan(&task_file.plan(&storage, prune)?, &file);
+    Ok(())
+}
+
+pub async fn handle_export(
+    slugs: Vec<String>,
+    format: Format,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let mut storage = Storage::load().await?;
+    if !slugs.is_empty() {
+        if let Some(missing) = slugs
+            .iter()
+            .find(|slug| !storage.events.iter().any(|e| &e.slug == *slug))
+        {
+            return Err(anyhow::anyhow!("Task with slug '{}' not found", missing));
+        }
+        storage.events.retain(|e| slugs.contains(&e.slug));
+    }
+
+    let content = match format {
+        Format::Toml => TaskFile::from_storage(&storage).to_toml()?,
+        Format::Crontab => export::crontab(&storage.events)?,
+        Format::Systemd => {
+            let units = export::systemd_units(&storage.events)?;
+            return write_units(&units, &storage.events, output).await;
+        }
+    };
+
+    match output {
+        Some(path) => {
+            tokio::fs::write(&path, content).await?;
+            println!(
+                "Exported {} task(s) to {}",
+                storage.events.len(),
+                path.display()
+            );
+        }
+        None => print!("{content}"),
+    }
+
+    Ok(())
+}
+
+/// Write systemd units into `dir`, or print them one after another.
+async fn write_units(units: &[export::File], events: &[Event], dir: Option<PathBuf>) -> Result<()> {
+    let Some(dir) = dir else {
+        for (i, unit) in units.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            println!("# {}", unit.name);
+            print!("{}", unit.contents);
+        }
+        return Ok(());
+    };
+
+    tokio::fs::create_dir_all(&dir).await?;
+    for unit in units {
+        tokio::fs::write(dir.join(&unit.name), &unit.contents).await?;
+    }
+    println!("Wrote {} unit file(s) to {}", units.len(), dir.display());
+
+    let timers: Vec<String> = events
+        .iter()
+        .filter(|e| e.active)
+        .map(|e| format!("{}.timer", export::unit_name(&e.slug)))
+        .collect();
+    if !timers.is_empty() {
+        println!("Once they are in ~/.config/systemd/user, enable them with:");
+        println!("  systemctl --user daemon-reload");
+        println!("  systemctl --user enable --now {}", timers.join(" "));
+        println!("and remove the tasks from singleschedule so they don't run twice.");
+    }
+
+    Ok(())
+}
+
+pub async fn handle_import_crontab(file: Option<PathBuf>, dry_run: bool) -> Result<()> {
+    let content = read_crontab(file.as_deref()).await?;
+
+    let import = if dry_run {
+        let storage = Storage::load().await?;
+        crontab::parse(&content, &taken_slugs(&storage))
+    } else {
+        Storage::update(|storage| {
+            let import = crontab::parse(&content, &taken_slugs(storage));
+            for event in &import.events {
+                storage.insert_event(event.clone())?;
+            }
+            Ok(import)
+        })
+        .await?
+    };
+
+    print_import(&import, dry_run);
+    if dry_run || import.events.is_empty() {
+        return Ok(());
+    }
+
+    // Have the daemon pick up the changes
+    if let Err(e) = crate::daemon::reload_daemon().await {
+        eprintln!("Warning: Failed to reload daemon: {e}");
+        eprintln!("Please start the daemon manually with 'singleschedule start'");
+    }
+
+    Ok(())
+}
+
+async fn read_crontab(file: Option<&std::path::Path>) -> Result<String> {
+    match file {
+        Some(path) if path.as_os_str() == "-" => {
+            let mut content = String::new();
+            tokio::io::stdin().read_to_string(&mut content).await?;
+            Ok(content)
+        }
+        Some(path) => tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e)),
+        None => {
+            let output = tokio::process::Command::new("crontab")
+                .arg("-l")
+                .output()
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to run 'crontab -l': {}", e))?;
+            if !output.status.success() {
+                return Err(anyhow::anyhow!(
+                    "'crontab -l' failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        }
+    }
+}
+
+fn taken_slugs(storage: &Storage) -> std::collections::HashSet<String> {
+    storage.events.iter().map(|e| e.slug.clone()).collect()
+}
+
+fn print_import(import: &Import, dry_run: bool) {
+    let verb = if dry_run { "Would import" } else { "Imported" };
+    println!("{} {} task(s)", verb, import.events.len());
+    for event in &import.events {
+        println!("+ {} ({}) {}", event.slug, event.cron, event.command);
+    }
+
+    if !import.skipped.is_empty() {
+        println!();
+        println!("{} line(s) could not be imported:", import.skipped.len());
+        for skipped in &import.skipped {
+            println!("  line {}: {}", skipped.line, skipped.reason);
+            println!("    {}", skipped.text.trim());
+        }
+    }
+}
+
+/// Print a plan the way `diff` shows it: `+` added, `~` changed, `-` removed.
+fn print_plan(plan: &Plan, file: &std::path::Path) {
+    if plan.changes.is_empty() {
+        println!("Tasks are up to date with {}", file.display());
+    }
+
+    for change in &plan.changes {
+        match change {
+            Change::Add(event) => {
+                println!("+ {} ({}) {}", event.slug, event.cron, event.command)
+            }
+            Change::Update { event, fields } => {
+                println!("~ {}", event.slug);
+                for field in fields {
+                    println!("    {}: {} -> {}", field.field, field.old, field.new);
+                }
+            }
+            Change::Remove(slug) => println!("- {slug}"),
+        }
+    }
+
+    if !plan.kept.is_empty() {
+        println!(
+            "{} task(s) not in {} are kept (use --prune to remove them): {}",
+            plan.kept.len(),
+            file.display(),
+            plan.kept.join(", ")
+        );
+    }
+}
+
+/// Stream a running run's live log until the run finishes, or until the
+/// scheduler that was running it is gone.
+async fn follow_run(slug: &str, id: u64) -> Result<()> {
+    let path = History::live_log_path(slug, id)?;
+
+    // The runner removes the live log once the run is recorded; an open handle
+    // keeps it readable until we have drained it.
+    let mut file = match tokio::fs::File::open(&path).await {
+        Ok(file) => file,
+        Err(_) => {
+            // Finished between loading the history and opening the log
+            let history = History::load(slug).await?;
+            if let Some(record) = history.get(id) {
+                print_run_output(record);
+            }
+            return Ok(());
+        }
+    };
+
+    let mut stdout = tokio::io::stdout();
+    let mut buf = vec![0u8; 8192];
+    let mut finished = false;
+
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n > 0 {
+            stdout.write_all(&buf[..n]).await?;
+            stdout.flush().await?;
+            continue;
+        }
+
+        if finished {
+            break;
+        }
+
+        // Checked first, so a run the scheduler recorded on its way out isn't
+        // taken for one it abandoned
+        let scheduler_running = crate::daemon::is_running().await?;
+        let history = History::load(slug).await?;
+        match history.get(id) {
+            Some(record) if record.status == RunStatus::Running && scheduler_running => {
+                tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+            }
+            Some(record) if record.status == RunStatus::Running => {
+                // The scheduler died mid-run and will never record how it ended
+                finished = true;
+                let record = RunRecord {
+                    status: RunStatus::Interrupted,
+                    ..record.clone()
+                };
+                eprintln!("{}", run_summary(&record));
+            }
+            Some(record) => {
+                // Drain whatever was written before the run completed
+                finished = true;
+                eprintln!("{}", run_summary(record));
+            }
+            None => finished = true,
+        }
+    }
+
+    Ok(())
+}
+
+fn print_run_output(record: &RunRecord) {
+    print!("{}", record.stdout);
+    eprint!("{}", record.stderr);
+
+    if let Some(error) = &record.error {
+        eprintln!("Failed to start: {error}");
+    }
+    if record.output_truncated {
+        eprintln!("(output truncated to the last {MAX_OUTPUT_BYTES} bytes per stream)");
+    }
+
+    eprintln!("{}", run_summary(record));
+}
+
+fn run_summary(record: &RunRecord) -> String {
+    let mut summary = format!(
+        "Run {} {} at {}",
+        record.id,
+        record.status,
+        schedule::format_local(record.started_at)
+    );
+
+    if let Some(ms) = record.duration_ms {
+        summary.push_str(&format!(" after {}", format_duration_ms(ms)));
+    }
+    if let Some(code) = record.exit_code {
+        summary.push_str(&format!(" (exit code {code})"));
+    } else if let Some(signal) = record.signal {
+        summary.push_str(&format!(" (signal {signal})"));
+    }
+
+    summary
+}
+
+fn format_duration_ms(ms: u64) -> String {
+    if ms < 1000 {
+        format!("{ms}ms")
+    } else if ms < 60_000 {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    } else if ms < 3_600_000 {
+        format!("{}m{:02}s", ms / 60_000, (ms / 1000) % 60)
+    } else {
+        format!("{}h{:02}m", ms / 3_600_000, (ms / 60_000) % 60)
+    }
+}
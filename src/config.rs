@@ -0,0 +1,56 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::fs;
+
+use crate::backend::BackendKind;
+use crate::paths;
+
+/// Daemon-wide settings read from `config.json` in the config directory.
+///
+/// Every field has a default, so a missing file or missing keys are fine.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Maximum number of task runs executing at the same time.
+    pub max_concurrent_runs: usize,
+    /// Where tasks, runtime state and run history are kept.
+    pub storage_backend: BackendKind,
+    /// Seconds the daemon waits on shutdown for running tasks to finish
+    /// before interrupting them.
+    pub drain_timeout: u64,
+    /// Reload tasks as soon as `events.json` changes on disk, rather than
+    /// only on SIGHUP or the periodic re-read (JSON backend only).
+    pub watch_task_file: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_concurrent_runs: 8,
+            storage_backend: BackendKind::default(),
+            drain_timeout: 30,
+            watch_task_file: true,
+        }
+    }
+}
+
+impl Config {
+    pub async fn load() -> Result<Self> {
+        Self::load_from(&paths::config_dir()?).await
+    }
+
+    /// Load `config.json` from the given config directory.
+    pub async fn load_from(dir: &Path) -> Result<Self> {
+        let path = dir.join("config.json");
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path).await?;
+        let config: Config = serde_json::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Invalid config file {}: {}", path.display(), e))?;
+        Ok(config)
+    }
+}
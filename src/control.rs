@@ -0,0 +1,257 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::io::ErrorKind;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinSet;
+
+use crate::paths;
+
+/// How long a client waits for the daemon to answer.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest request line the daemon reads.
+const MAX_REQUEST_BYTES: u64 = 64 * 1024;
+
+/// A request to the daemon: one line of JSON such as `{"command":"status"}`
+/// or `{"command":"trigger","slug":"backup"}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// How the daemon is doing
+    Status,
+    /// Every task with its live state
+    List,
+    /// Run a task now, outside its schedule
+    Trigger { slug: String },
+    /// Mark tasks inactive; no slugs means every task
+    Pause {
+        #[serde(default)]
+        slugs: Vec<String>,
+    },
+    /// Mark tasks active; no slugs means every task
+    Resume {
+        #[serde(default)]
+        slugs: Vec<String>,
+    },
+    /// Re-read the task list, as on SIGHUP
+    Reload,
+    /// Drain running tasks and exit, as on SIGTERM
+    Shutdown,
+}
+
+/// The daemon's answer: `{"ok": ...}` or `{"error": "message"}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Ok(Reply),
+    Error(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reply {
+    /// Answer to `status`
+    Status(DaemonStatus),
+    /// Answer to `list`
+    Tasks(Vec<TaskStatus>),
+    /// Answer to `pause` and `resume`
+    Updated {
+        /// Tasks the request named (every task if it named none).
+        found: usize,
+        /// Those of them whose state actually changed.
+        changed: usize,
+        /// Slugs that named no task.
+        missing: Vec<String>,
+    },
+    /// Answer to `shutdown`; the daemon exits once its runs are drained
+    Stopping { pid: u32 },
+    /// Answer to `trigger` and `reload`
+    Done,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    pub pid: u32,
+    pub started_at: DateTime<Utc>,
+    pub tasks: usize,
+    pub active_tasks: usize,
+    /// Runs in progress or waiting for a free slot.
+    pub running: usize,
+    /// Runs waiting for the previous run of their task to finish.
+    pub queued: usize,
+    pub next_fire: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskStatus {
+    pub slug: String,
+    pub cron: String,
+    pub command: String,
+    pub active: bool,
+    /// PIDs of the task's runs in progress.
+    pub pids: Vec<u32>,
+    pub queued: usize,
+    pub last_run: Option<DateTime<Utc>>,
+    pub next_run: Option<DateTime<Utc>>,
+}
+
+/// A request received by the daemon, with where to send the response.
+pub struct Call {
+    pub request: Request,
+    pub respond: oneshot::Sender<Response>,
+}
+
+/// The daemon's control socket, next to its PID file.
+pub fn socket_path() -> Result<PathBuf> {
+    Ok(paths::state_dir()?.join("control.sock"))
+}
+
+/// Send a request to the daemon.
+///
+/// `None` means no daemon is listening, so callers can fall back to working
+/// on storage directly; an error response becomes an `Err`.
+pub async fn request(request: &Request) -> Result<Option<Reply>> {
+    let path = socket_path()?;
+    let stream = match UnixStream::connect(&path).await {
+        Ok(stream) => stream,
+        // No socket, or one left behind by a daemon that died
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => {
+            return Ok(None)
+        }
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to connect to {}", path.display()))
+        }
+    };
+
+    let response = tokio::time::timeout(REQUEST_TIMEOUT, exchange(stream, request))
+        .await
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "Daemon did not answer within {}s",
+                REQUEST_TIMEOUT.as_secs()
+            )
+        })??;
+    match response {
+        Response::Ok(reply) => Ok(Some(reply)),
+        Response::Error(message) => Err(anyhow::anyhow!(message)),
+    }
+}
+
+/// Error for a reply that doesn't fit the request.
+pub fn unexpected(reply: Reply) -> anyhow::Error {
+    anyhow::anyhow!("Unexpected reply from daemon: {:?}", reply)
+}
+
+async fn exchange(stream: UnixStream, request: &Request) -> Result<Response> {
+    let (read, mut write) = stream.into_split();
+    write_line(&mut write, request).await?;
+
+    let mut line = String::new();
+    BufReader::new(read).read_line(&mut line).await?;
+    if line.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Daemon closed the connection without answering"
+        ));
+    }
+    serde_json::from_str(&line).context("Invalid response from daemon")
+}
+
+/// Bind the control socket, readable by the owner only.
+///
+/// Fails if another scheduler is listening on it; a socket left behind by
+/// one that died is replaced.
+pub async fn bind() -> Result<UnixListener> {
+    let path = socket_path()?;
+    if path.exists() {
+        if UnixStream::connect(&path).await.is_ok() {
+            return Err(anyhow::anyhow!(
+                "Another scheduler is already listening on {}",
+                path.display()
+            ));
+        }
+        std::fs::remove_file(&path)?;
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let listener =
+        UnixListener::bind(&path).with_context(|| format!("Failed to bind {}", path.display()))?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Accept connections until `stop` completes, passing each one's request on
+/// to `calls`; clients already connected then still get their answer.
+pub async fn serve(
+    listener: UnixListener,
+    calls: mpsc::UnboundedSender<Call>,
+    stop: impl Future<Output = ()>,
+) {
+    tokio::pin!(stop);
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    connections.spawn(answer(stream, calls.clone()));
+                }
+                Err(e) => warn!("Failed to accept control connection: {e}"),
+            },
+            Some(finished) = connections.join_next() => log_failure(finished),
+            _ = &mut stop => break,
+        }
+    }
+
+    drop(listener);
+    while let Some(finished) = connections.join_next().await {
+        log_failure(finished);
+    }
+}
+
+fn log_failure(finished: Result<Result<()>, tokio::task::JoinError>) {
+    if let Ok(Err(e)) = finished {
+        debug!("Control connection failed: {e:#}");
+    }
+}
+
+/// Read one request from a client and write back the scheduler's response.
+async fn answer(stream: UnixStream, calls: mpsc::UnboundedSender<Call>) -> Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut line = String::new();
+    BufReader::new(read.take(MAX_REQUEST_BYTES))
+        .read_line(&mut line)
+        .await?;
+
+    let response = match serde_json::from_str::<Request>(&line) {
+        Ok(request) => {
+            let (respond, response) = oneshot::channel();
+            calls
+                .send(Call { request, respond })
+                .map_err(|_| anyhow::anyhow!("Scheduler is not running"))?;
+            response
+                .await
+                .unwrap_or_else(|_| Response::Error("Scheduler is shutting down".to_string()))
+        }
+        Err(e) => Response::Error(format!("Invalid request: {e}")),
+    };
+    write_line(&mut write, &response).await
+}
+
+async fn write_line(
+    write: &mut (impl AsyncWriteExt + Unpin),
+    value: &impl Serialize,
+) -> Result<()> {
+    let mut line = serde_json::to_string(value)?;
+    line.push('\n');
+    write.write_all(line.as_bytes()).await?;
+    Ok(())
+}
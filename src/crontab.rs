@@ -0,0 +1,264 @@
+use std::collections::HashSet;
+
+use crate::schedule::{self, TaskSchedule};
+use crate::storage::{Event, DEFAULT_SHELL};
+
+/// Tasks translated from a crontab, with the lines that had no translation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Import {
+    pub events: Vec<Event>,
+    pub skipped: Vec<Skipped>,
+}
+
+/// A crontab line that was not imported, and why.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skipped {
+    /// 1-based line number.
+    pub line: usize,
+    pub text: String,
+    pub reason: String,
+}
+
+/// Translate a user crontab into tasks, avoiding the slugs in `taken`.
+///
+/// Jobs become shell-mode tasks run by `$SHELL` (`/bin/sh` by default), with
+/// the crontab's own semantics spelled out in the script:
+///
+/// - variable assignments (other than `SHELL`, `CRON_TZ` and `MAILTO`) are
+///   exported at the start of each later job's script
+/// - `CRON_TZ` sets the timezone of later jobs
+/// - text after an unescaped `%` is fed to the command on stdin, with any
+///   further `%` turned into newlines
+///
+/// A trailing `# comment` names the task; without one the slug is derived
+/// from the program the job runs.
+pub fn parse(content: &str, taken: &HashSet<String>) -> Import {
+    let mut import = Import::default();
+    let mut taken = taken.clone();
+    let mut shell = DEFAULT_SHELL.to_string();
+    let mut timezone: Option<String> = None;
+    let mut exports: Vec<(String, String)> = Vec::new();
+
+    for (index, text) in content.lines().enumerate() {
+        let line = text.trim();
+        let mut skip = |reason: String| {
+            import.skipped.push(Skipped {
+                line: index + 1,
+                text: text.to_string(),
+                reason,
+            })
+        };
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some((name, value)) = parse_assignment(line) {
+            match name.as_str() {
+                "SHELL" => shell = value,
+                "CRON_TZ" => match schedule::parse_timezone(&value) {
+                    Ok(_) => timezone = Some(value),
+                    Err(e) => skip(format!("{e:#}")),
+                },
+                "MAILTO" | "MAILFROM" => skip(format!(
+                    "{name} is not supported; run output is kept in the task's run history"
+                )),
+                _ => {
+                    exports.retain(|(existing, _)| existing != &name);
+                    exports.push((name, value));
+                }
+            }
+            continue;
+        }
+
+        let Some((cron, command)) = split_schedule(line) else {
+            skip("not a schedule followed by a command".to_string());
+            continue;
+        };
+        if let Err(e) = TaskSchedule::new(&cron, timezone.as_deref()) {
+            skip(format!("{e:#}"));
+            continue;
+        }
+
+        // The name comes off first, so it never ends up in the input
+        let (command, comment) = strip_comment(command);
+        let (command, stdin) = split_stdin(command);
+        let command = command.trim();
+        if command.is_empty() {
+            skip("empty command".to_string());
+            continue;
+        }
+
+        let slug = match comment.map(|comment| slugify(&comment)) {
+            Some(slug) if !slug.is_empty() => {
+                if taken.contains(&slug) {
+                    skip(format!("a task named '{slug}' already exists"));
+                    continue;
+                }
+                slug
+            }
+            _ => unique_slug(&program_name(command), &taken),
+        };
+        taken.insert(slug.clone());
+
+        let mut script: String = exports
+            .iter()
+            .map(|(name, value)| format!("export {}={}; ", name, shell_quote(value)))
+            .collect();
+        match stdin {
+            Some(lines) => {
+                let lines: Vec<String> = lines.iter().map(|line| shell_quote(line)).collect();
+                script.push_str(&format!(
+                    "printf '%s\\n' {} | ( {} )",
+                    lines.join(" "),
+                    command
+                ));
+            }
+            None => script.push_str(command),
+        }
+
+        import.events.push(Event {
+            slug,
+            cron,
+            command: script,
+            shell: Some(shell.clone()),
+            timezone: timezone.clone(),
+            active: true,
+            ..Default::default()
+        });
+    }
+
+    import
+}
+
+/// `NAME = value`, with the value optionally in matching quotes.
+fn parse_assignment(line: &str) -> Option<(String, String)> {
+    let (name, value) = line.split_once('=')?;
+    let name = name.trim();
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return None;
+    }
+
+    let value = value.trim();
+    let unquoted = ['"', '\'']
+        .iter()
+        .find_map(|&quote| value.strip_prefix(quote)?.strip_suffix(quote))
+        .unwrap_or(value);
+    Some((name.to_string(), unquoted.to_string()))
+}
+
+/// Split a job line into its schedule (an `@`-macro or five fields) and the
+/// rest of the line.
+fn split_schedule(line: &str) -> Option<(String, &str)> {
+    let fields = if line.starts_with('@') { 1 } else { 5 };
+
+    let mut rest = line;
+    let mut schedule = Vec::with_capacity(fields);
+    for _ in 0..fields {
+        let (field, remainder) = rest.split_once(char::is_whitespace)?;
+        schedule.push(field);
+        rest = remainder.trim_start();
+    }
+
+    Some((schedule.join(" "), rest))
+}
+
+/// Cut the command at the first unescaped `%`; the rest becomes stdin, one
+/// line per further `%`. `\%` stands for a literal `%` in both parts.
+fn split_stdin(command: &str) -> (String, Option<Vec<String>>) {
+    let mut parts = vec![String::new()];
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'%') => {
+                parts.last_mut().unwrap().push('%');
+                chars.next();
+            }
+            '%' => parts.push(String::new()),
+            _ => parts.last_mut().unwrap().push(c),
+        }
+    }
+
+    // Input always ends in a newline, so a trailing `%` adds no empty line
+    if parts.len() > 2 && parts.last().is_some_and(String::is_empty) {
+        parts.pop();
+    }
+
+    let command = parts.remove(0);
+    let stdin = (!parts.is_empty()).then_some(parts);
+    (command, stdin)
+}
+
+/// Split off a trailing shell comment: a `#` starting a word outside quotes.
+fn strip_comment(command: &str) -> (&str, Option<String>) {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut previous = ' ';
+
+    for (i, c) in command.char_indices() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '#') if previous.is_whitespace() => {
+                let comment = command[i + 1..].trim().to_string();
+                return (command[..i].trim(), Some(comment));
+            }
+            _ => {}
+        }
+        previous = c;
+    }
+
+    (command.trim(), None)
+}
+
+/// Name of the program a command runs, skipping leading assignments.
+fn program_name(command: &str) -> String {
+    let program = command
+        .split_whitespace()
+        .find(|word| parse_assignment(word).is_none())
+        .unwrap_or("");
+    let name = program.rsplit('/').next().unwrap_or(program);
+    let name = match name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => name,
+    };
+    slugify(name)
+}
+
+/// Lowercase letters, digits, `.` and `_`, with anything else collapsed into
+/// single dashes.
+fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_matches(|c| c == '-' || c == '.').to_string()
+}
+
+fn unique_slug(base: &str, taken: &HashSet<String>) -> String {
+    let base = if base.is_empty() { "job" } else { base };
+    if !taken.contains(base) {
+        return base.to_string();
+    }
+    (2..)
+        .map(|n| format!("{base}-{n}"))
+        .find(|slug| !taken.contains(slug))
+        .unwrap()
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
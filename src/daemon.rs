@@ -1,89 +1,253 @@
 use anyhow::Result;
-use log::{error, info};
+use log::{info, warn};
+use nix::fcntl::{fcntl, FcntlArg, FdFlag, OFlag};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use std::fs;
-use std::path::PathBuf;
-use tokio::signal;
-
+use std::io::Write;
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::Notify;
+
+use crate::backend::BackendKind;
+use crate::config::Config;
+use crate::control::{self, Reply, Request};
+use crate::paths;
+use crate::runner::TERMINATE_GRACE_PERIOD;
 use crate::scheduler::Scheduler;
+use crate::sd_notify::Notifier;
+
+/// Set to make `start_daemon`, `stop_daemon` and `reload_daemon` do nothing,
+/// so tests can drive the CLI handlers without a daemon. Only debug builds
+/// look at it; release builds always manage the daemon.
+pub const NO_DAEMON_ENV: &str = "SINGLESCHEDULE_NO_DAEMON";
+
+/// Descriptor of the pipe a daemon being started reports back on.
+const READY_FD_ENV: &str = "SINGLESCHEDULE_READY_FD";
 
+/// What the daemon writes to the readiness pipe once its tasks are loaded;
+/// anything else is the error it failed with.
+const READY: &str = "ready";
+
+/// How long `start_daemon` waits for the daemon to report back.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Start the daemon and wait until it has loaded its tasks.
+///
+/// Forking this process would take its runtime's worker threads along only in
+/// name, so the daemon is a fresh `run --daemonize` process that forks before
+/// building a runtime of its own (see [`daemonize`]). It reports readiness, or
+/// why it failed to start, over a pipe.
 pub async fn start_daemon() -> Result<()> {
     // Skip daemon functionality in test mode
-    #[cfg(test)]
-    {
-        if std::env::var("SINGLESCHEDULE_TEST_HOME").is_ok() {
-            info!("Skipping daemon start in test mode");
-            return Ok(());
-        }
+    if daemon_disabled() {
+        info!("Skipping daemon start ({NO_DAEMON_ENV} is set)");
+        return Ok(());
     }
 
-    let pid_file = get_pid_file()?;
-
     // Check if daemon is already running
-    if pid_file.exists() {
-        let pid = fs::read_to_string(&pid_file)?.trim().parse::<u32>()?;
-        if is_process_running(pid) {
-            return Err(anyhow::anyhow!(
-                "Daemon is already running with PID {}",
-                pid
-            ));
-        }
-        // Clean up stale PID file
-        fs::remove_file(&pid_file)?;
+    if let Some(pid) = running_pid()? {
+        return Err(anyhow::anyhow!(
+            "Daemon is already running with PID {}",
+            pid
+        ));
     }
 
-    // Fork the daemon process
-    let daemon = daemonize::Daemonize::new()
+    // Only the write end is inherited
+    let (ready_rx, ready_tx) = nix::unistd::pipe2(OFlag::O_CLOEXEC)?;
+    fcntl(ready_tx.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::empty()))?;
+
+    let mut child = tokio::process::Command::new(std::env::current_exe()?)
+        .args(["run", "--daemonize"])
+        .env(READY_FD_ENV, ready_tx.as_raw_fd().to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to start daemon: {}", e))?;
+    drop(ready_tx);
+    // It exits as soon as it has forked the daemon
+    child.wait().await?;
+
+    // The pipe reaches EOF once the daemon has reported back, or has died
+    let mut ready = tokio::net::unix::pipe::Receiver::from_owned_fd(ready_rx)?;
+    let mut report = String::new();
+    tokio::time::timeout(STARTUP_TIMEOUT, ready.read_to_string(&mut report))
+        .await
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "Daemon did not report back within {}s",
+                STARTUP_TIMEOUT.as_secs()
+            )
+        })??;
+
+    match report.as_str() {
+        READY => Ok(()),
+        "" => Err(anyhow::anyhow!("Daemon exited before it was ready")),
+        error => Err(anyhow::anyhow!("Failed to start daemon: {}", error)),
+    }
+}
+
+/// Become the daemon `start_daemon` asked for: fork away from the invoking
+/// process and run the scheduler on a runtime built afterwards.
+///
+/// Must be called before any runtime or other thread exists, as forking only
+/// carries the calling thread over into the child.
+pub fn daemonize() -> Result<()> {
+    let mut ready = ReadyPipe::from_env();
+    let result = daemonize_and_run(&mut ready);
+    if let Err(e) = &result {
+        ready.fail(e);
+    }
+    result
+}
+
+fn daemonize_and_run(ready: &mut ReadyPipe) -> Result<()> {
+    let pid_file = get_pid_file()?;
+
+    // Fork the daemon process; the parent exits here
+    daemonize::Daemonize::new()
         .pid_file(&pid_file)
         .working_directory("/tmp")
-        .umask(0o027);
+        .umask(0o027)
+        .start()
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+    info!("Daemon started successfully");
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    let result = runtime.block_on(run_scheduler(None, || ready.ready()));
+    remove_pid_file(&pid_file)?;
+    result
+}
+
+/// Write end of the pipe `start_daemon` waits on, if started by it.
+struct ReadyPipe(Option<fs::File>);
+
+impl ReadyPipe {
+    fn from_env() -> Self {
+        let fd = std::env::var(READY_FD_ENV)
+            .ok()
+            .and_then(|fd| fd.parse::<RawFd>().ok());
+        // Still single-threaded, so changing the environment is safe; tasks
+        // inherit neither the variable nor the descriptor
+        std::env::remove_var(READY_FD_ENV);
+        let pipe = fd.and_then(|fd| {
+            fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC)).ok()?;
+            // SAFETY: the descriptor was opened for this process by
+            // `start_daemon` and nothing else owns it
+            Some(unsafe { fs::File::from_raw_fd(fd) })
+        });
+        ReadyPipe(pipe)
+    }
 
-    match daemon.start() {
-        Ok(_) => {
-            info!("Daemon started successfully");
-            run_scheduler().await?;
+    /// Report that the daemon is up, closing the pipe.
+    fn ready(&mut self) {
+        if let Some(mut pipe) = self.0.take() {
+            let _ = pipe.write_all(READY.as_bytes());
         }
-        Err(e) => return Err(anyhow::anyhow!("Failed to start daemon: {}", e)),
     }
 
-    Ok(())
+    /// Report why the daemon failed, unless it already reported readiness.
+    fn fail(&mut self, error: &anyhow::Error) {
+        if let Some(mut pipe) = self.0.take() {
+            let _ = write!(pipe, "{error:#}");
+        }
+    }
+}
+
+/// Run the scheduler in this process until SIGTERM or SIGINT, for running
+/// under a supervisor such as systemd, supervisord or a container runtime.
+///
+/// No PID file is written unless `pid_file` is given. Readiness, shutdown and
+/// watchdog pings are reported to `notifier`, built from `$NOTIFY_SOCKET`
+/// before the runtime started. An error means the scheduler failed, rather
+/// than being asked to stop.
+pub async fn run_foreground(pid_file: Option<PathBuf>, notifier: Option<Notifier>) -> Result<()> {
+    if let Some(pid_file) = &pid_file {
+        if let Some(pid) = pid_in(pid_file)? {
+            return Err(anyhow::anyhow!(
+                "Scheduler is already running with PID {} ({})",
+                pid,
+                pid_file.display()
+            ));
+        }
+        if let Some(dir) = pid_file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(pid_file, format!("{}\n", std::process::id()))?;
+    }
+
+    let result = run_scheduler(notifier.map(Arc::new), || {}).await;
+
+    if let Some(pid_file) = &pid_file {
+        remove_pid_file(pid_file)?;
+    }
+    result
 }
 
 pub async fn stop_daemon() -> Result<()> {
     // Skip daemon functionality in test mode
-    #[cfg(test)]
-    {
-        if std::env::var("SINGLESCHEDULE_TEST_HOME").is_ok() {
-            info!("Skipping daemon stop in test mode");
-            return Ok(());
-        }
+    if daemon_disabled() {
+        info!("Skipping daemon stop ({NO_DAEMON_ENV} is set)");
+        return Ok(());
     }
 
     let pid_file = get_pid_file()?;
 
-    if !pid_file.exists() {
-        return Err(anyhow::anyhow!("Daemon is not running"));
-    }
+    let pid = match control::request(&Request::Shutdown).await? {
+        Some(Reply::Stopping { pid }) => pid,
+        Some(reply) => return Err(control::unexpected(reply)),
+        // Not listening on its control socket; fall back to the PID file
+        None => {
+            if !pid_file.exists() {
+                return Err(anyhow::anyhow!("Daemon is not running"));
+            }
 
-    let pid = fs::read_to_string(&pid_file)?.trim().parse::<u32>()?;
+            let pid = fs::read_to_string(&pid_file)?.trim().parse::<u32>()?;
 
-    if !is_process_running(pid) {
-        fs::remove_file(&pid_file)?;
-        return Err(anyhow::anyhow!(
-            "Daemon is not running (stale PID file removed)"
-        ));
-    }
+            if !is_process_running(pid) {
+                fs::remove_file(&pid_file)?;
+                return Err(anyhow::anyhow!(
+                    "Daemon is not running (stale PID file removed)"
+                ));
+            }
 
-    // Send SIGTERM to the daemon
-    use nix::sys::signal::{self, Signal};
-    use nix::unistd::Pid;
+            // Send SIGTERM to the daemon
+            use nix::sys::signal::{self, Signal};
+            use nix::unistd::Pid;
 
-    signal::kill(Pid::from_raw(pid as i32), Signal::SIGTERM)
-        .map_err(|e| anyhow::anyhow!("Failed to stop daemon: {}", e))?;
+            signal::kill(Pid::from_raw(pid as i32), Signal::SIGTERM)
+                .map_err(|e| anyhow::anyhow!("Failed to stop daemon: {}", e))?;
+            pid
+        }
+    };
 
-    // Wait a bit for the process to terminate
-    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    // The daemon lets running tasks finish first, interrupting them after the
+    // drain timeout; allow for that and for them to exit
+    let config = Config::load().await?;
+    let limit = Duration::from_secs(config.drain_timeout) + TERMINATE_GRACE_PERIOD * 2;
+    let started = tokio::time::Instant::now();
+    let mut waiting_noted = false;
+    while is_process_running(pid) {
+        if started.elapsed() >= limit {
+            return Err(anyhow::anyhow!(
+                "Daemon (PID {}) did not exit within {}s",
+                pid,
+                limit.as_secs()
+            ));
+        }
+        if !waiting_noted && started.elapsed() >= Duration::from_secs(1) {
+            println!("Waiting for running tasks to finish...");
+            waiting_noted = true;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
 
-    // Clean up PID file
+    // The daemon removes its PID file itself, unless it died
     if pid_file.exists() {
         fs::remove_file(&pid_file)?;
     }
@@ -92,76 +256,191 @@ pub async fn stop_daemon() -> Result<()> {
     Ok(())
 }
 
-pub async fn restart_daemon() -> Result<()> {
+/// Have the daemon pick up changed tasks: ask a running daemon to reload
+/// (keeping runs in progress), or start one if none is running.
+pub async fn reload_daemon() -> Result<()> {
     // Skip daemon functionality in test mode
-    #[cfg(test)]
-    {
-        if std::env::var("SINGLESCHEDULE_TEST_HOME").is_ok() {
-            info!("Skipping daemon restart in test mode");
-            return Ok(());
-        }
+    if daemon_disabled() {
+        info!("Skipping daemon reload ({NO_DAEMON_ENV} is set)");
+        return Ok(());
+    }
+
+    if control::request(&Request::Reload).await?.is_some() {
+        return Ok(());
     }
+    let Some(pid) = running_pid()? else {
+        return start_daemon().await;
+    };
 
-    // Try to stop existing daemon
-    let _ = stop_daemon().await;
+    use nix::sys::signal::{self, Signal};
+    use nix::unistd::Pid;
 
-    // Start new daemon
-    start_daemon().await
+    signal::kill(Pid::from_raw(pid as i32), Signal::SIGHUP)
+        .map_err(|e| anyhow::anyhow!("Failed to reload daemon: {}", e))
 }
 
-async fn run_scheduler() -> Result<()> {
+/// Run the scheduler until SIGTERM or SIGINT, calling `on_ready` once its
+/// tasks are loaded, then drain running tasks.
+async fn run_scheduler(notifier: Option<Arc<Notifier>>, on_ready: impl FnOnce()) -> Result<()> {
     info!("Starting scheduler");
 
-    let mut scheduler = Scheduler::new();
+    let config = Config::load().await?;
+    let mut scheduler = Scheduler::with_config(&config);
     scheduler.load_events().await?;
 
-    // Set up signal handler for graceful shutdown
-    let shutdown_signal = async {
-        let _ = signal::ctrl_c().await;
-        info!("Received shutdown signal");
+    // `stop` sends SIGTERM; SIGINT covers Ctrl-C when run in a terminal
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sigint = signal(SignalKind::interrupt())?;
+    let shutdown_signal = async move {
+        let name = tokio::select! {
+            _ = sigterm.recv() => "SIGTERM",
+            _ = sigint.recv() => "SIGINT",
+        };
+        info!("Received {name}, shutting down");
     };
 
-    tokio::select! {
-        result = scheduler.run() => {
-            if let Err(e) = result {
-                error!("Scheduler error: {e}");
+    // `reload_daemon` sends SIGHUP after changing tasks
+    let reload = scheduler.reload_trigger();
+    let mut sighup = signal(SignalKind::hangup())?;
+    let reload_on_signal = reload.clone();
+    tokio::spawn(async move {
+        while sighup.recv().await.is_some() {
+            info!("Received SIGHUP");
+            reload_on_signal.notify_one();
+        }
+    });
+
+    // Kept alive until the scheduler stops
+    let _watcher = if config.watch_task_file && config.storage_backend == BackendKind::Json {
+        match watch_task_file(reload) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                warn!("Not watching the task file for changes: {e}");
+                None
             }
         }
-        _ = shutdown_signal => {
-            info!("Shutting down scheduler");
+    } else {
+        None
+    };
+
+    // Bound before reporting readiness, so a second scheduler fails to start
+    let listener = control::bind().await?;
+
+    // Only now is this the profile's one scheduler, so runs still marked as
+    // running were left behind by one that died
+    scheduler.interrupt_abandoned_runs().await?;
+    let stop_serving = Arc::new(Notify::new());
+    let server = tokio::spawn(control::serve(listener, scheduler.control_calls(), {
+        let stop_serving = stop_serving.clone();
+        async move { stop_serving.notified().await }
+    }));
+
+    on_ready();
+    if let Some(notifier) = &notifier {
+        notifier.notify(&format!("READY=1\nMAINPID={}", std::process::id()))?;
+        if let Some(interval) = notifier.watchdog_interval() {
+            scheduler.set_watchdog(notifier.clone(), interval);
         }
     }
 
-    // Clean up PID file on exit
-    let pid_file = get_pid_file()?;
-    if pid_file.exists() {
-        fs::remove_file(&pid_file)?;
+    let result = scheduler.run_until(shutdown_signal).await;
+
+    // Take no more requests; clients fall back to the PID file meanwhile
+    stop_serving.notify_one();
+    let _ = fs::remove_file(control::socket_path()?);
+    if let Some(notifier) = &notifier {
+        if let Err(e) = notifier.notify("STOPPING=1") {
+            warn!("{e:#}");
+        }
     }
+    scheduler
+        .drain(Duration::from_secs(config.drain_timeout))
+        .await;
+
+    // Requests the scheduler never got to fail once it is dropped, letting
+    // the server finish answering
+    drop(scheduler);
+    let _ = tokio::time::timeout(Duration::from_secs(1), server).await;
+
+    result
+}
 
+/// Remove a PID file, if it is still there.
+fn remove_pid_file(pid_file: &Path) -> Result<()> {
+    if pid_file.exists() {
+        fs::remove_file(pid_file)?;
+    }
     Ok(())
 }
 
-fn get_pid_file() -> Result<PathBuf> {
-    #[cfg(test)]
-    {
-        if let Ok(test_home) = std::env::var("SINGLESCHEDULE_TEST_HOME") {
-            let dir = PathBuf::from(test_home).join(".singleschedule");
-            fs::create_dir_all(&dir)?;
-            return Ok(dir.join("daemon.pid"));
+/// Ask for a reload whenever `events.json` is written, renamed into place or
+/// deleted, e.g. after editing it by hand.
+fn watch_task_file(reload: Arc<Notify>) -> Result<RecommendedWatcher> {
+    let dir = paths::config_dir()?;
+    fs::create_dir_all(&dir)?;
+
+    // Watch the directory, not the file: saves replace the file by renaming
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| {
+        let Ok(event) = result else {
+            return;
+        };
+        let written = matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+        );
+        let task_file = event
+            .paths
+            .iter()
+            .any(|path| path.file_name().is_some_and(|name| name == "events.json"));
+        if written && task_file {
+            reload.notify_one();
         }
+    })?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+
+    Ok(watcher)
+}
+
+/// Whether a scheduler, daemonized or in the foreground, answers on the
+/// control socket.
+pub async fn is_running() -> Result<bool> {
+    Ok(control::request(&Request::Status).await?.is_some())
+}
+
+/// PID of the running daemon, removing a stale PID file.
+fn running_pid() -> Result<Option<u32>> {
+    pid_in(&get_pid_file()?)
+}
+
+/// PID of the live process recorded in `pid_file`, removing the file if
+/// that process is gone.
+fn pid_in(pid_file: &Path) -> Result<Option<u32>> {
+    if !pid_file.exists() {
+        return Ok(None);
     }
 
-    let home = directories::UserDirs::new()
-        .ok_or_else(|| anyhow::anyhow!("Failed to get home directory"))?
-        .home_dir()
-        .to_path_buf();
+    let pid = fs::read_to_string(pid_file)?.trim().parse::<u32>()?;
+    if is_process_running(pid) {
+        return Ok(Some(pid));
+    }
+    fs::remove_file(pid_file)?;
+    Ok(None)
+}
 
-    let dir = home.join(".singleschedule");
+/// The daemon's PID file, which `stop` and the reloading commands look for.
+pub fn get_pid_file() -> Result<PathBuf> {
+    let dir = paths::state_dir()?;
     fs::create_dir_all(&dir)?;
 
     Ok(dir.join("daemon.pid"))
 }
 
+/// Whether starting and stopping the daemon is turned off, as the tests do
+/// when they drive the CLI handlers in-process.
+fn daemon_disabled() -> bool {
+    cfg!(debug_assertions) && std::env::var_os(NO_DAEMON_ENV).is_some()
+}
+
 fn is_process_running(pid: u32) -> bool {
     // Try to send signal 0 to check if process exists
     use nix::sys::signal;
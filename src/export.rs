@@ -0,0 +1,391 @@
+use anyhow::Result;
+use cron::TimeUnitSpec;
+
+use crate::schedule::TaskSchedule;
+use crate::storage::{Event, MisfirePolicy, DEFAULT_SHELL};
+
+/// What `export` writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// A TOML task file, as read by `apply`
+    #[default]
+    Toml,
+    /// A systemd user `.service` and `.timer` unit per task
+    Systemd,
+    /// Crontab lines
+    Crontab,
+}
+
+/// A generated file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct File {
+    pub name: String,
+    pub contents: String,
+}
+
+/// Days of the week in systemd's order; the cron crate counts 1-7 from Sunday.
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Name of the units running a task, without the `.service`/`.timer` suffix.
+pub fn unit_name(slug: &str) -> String {
+    let slug: String = slug
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':') => c,
+            _ => '_',
+        })
+        .collect();
+    format!("singleschedule-{slug}")
+}
+
+/// A `.service` and a `.timer` unit for each task.
+///
+/// Fails, naming every offending task, if any schedule has no `OnCalendar=`
+/// equivalent.
+pub fn systemd_units(events: &[Event]) -> Result<Vec<File>> {
+    let units = translate_all(events, |event| {
+        let schedule = TaskSchedule::for_event(event)?;
+        let (program, args) = event.program_and_args()?;
+        let name = unit_name(&event.slug);
+
+        let exec: Vec<String> = std::iter::once(&program)
+            .chain(&args)
+            .map(|arg| systemd_quote(arg))
+            .collect();
+        let mut service = format!(
+            "[Unit]\nDescription=singleschedule task {}\n\n[Service]\nType=oneshot\nExecStart={}\n",
+            event.slug,
+            exec.join(" ")
+        );
+        if let Some(timeout) = event.timeout {
+            service.push_str(&format!("TimeoutStartSec={timeout}\n"));
+        }
+
+        let mut timer = format!(
+            "[Unit]\nDescription=Schedule of singleschedule task {}\n\n[Timer]\n",
+            event.slug
+        );
+        match on_calendar(&schedule)? {
+            Some(calendar) => {
+                timer.push_str(&format!("OnCalendar={calendar}\nAccuracySec=1s\n"));
+                // systemd catches up once on missed runs, like run_once
+                if event.misfire_policy != MisfirePolicy::SkipToNext {
+                    timer.push_str("Persistent=true\n");
+                }
+            }
+            None => timer.push_str("OnStartupSec=0\n"),
+        }
+        timer.push_str("\n[Install]\nWantedBy=timers.target\n");
+
+        Ok([
+            File {
+                name: format!("{name}.service"),
+                contents: service,
+            },
+            File {
+                name: format!("{name}.timer"),
+                contents: timer,
+            },
+        ])
+    })?;
+
+    Ok(units.into_iter().flatten().collect())
+}
+
+/// Crontab lines for all tasks, each named by a trailing comment as
+/// `import-crontab` expects; inactive tasks are commented out.
+///
+/// Fails, naming every offending task, if any schedule or command can't be
+/// written as a crontab line.
+pub fn crontab(events: &[Event]) -> Result<String> {
+    let lines = translate_all(events, |event| {
+        let schedule = TaskSchedule::for_event(event)?;
+        let line = format!(
+            "{} {} # {}",
+            crontab_schedule(&schedule)?,
+            crontab_command(event)?,
+            event.slug
+        );
+        let timezone = schedule.timezone().name().to_string();
+        Ok((timezone, event.active, line))
+    })?;
+
+    let mut crontab = String::from(
+        "# Exported from singleschedule. CRON_TZ is honored by cronie; other\n\
+         # crons run every line in the system timezone.\n",
+    );
+    let mut current_timezone = None;
+    for (timezone, active, line) in lines {
+        if current_timezone.as_ref() != Some(&timezone) {
+            crontab.push_str(&format!("CRON_TZ={timezone}\n"));
+            current_timezone = Some(timezone);
+        }
+        if !active {
+            crontab.push_str("# ");
+        }
+        crontab.push_str(&line);
+        crontab.push('\n');
+    }
+
+    Ok(crontab)
+}
+
+/// The `OnCalendar=` spelling of a schedule, with its timezone (`None` for
+/// `@reboot`).
+pub fn on_calendar(schedule: &TaskSchedule) -> Result<Option<String>> {
+    let Some(cron) = schedule.cron() else {
+        return Ok(None);
+    };
+
+    let days_of_week = Field::of(cron.days_of_week(), 1, 7);
+    let weekdays = if days_of_week.is_all() {
+        String::new()
+    } else {
+        // Renumber from Sunday = 1 to Monday = 1 so runs of days stay together
+        let mut days: Vec<u32> = days_of_week
+            .values
+            .iter()
+            .map(|day| (day + 5) % 7 + 1)
+            .collect();
+        days.sort_unstable();
+        let days = Field {
+            values: days,
+            min: 1,
+            max: 7,
+        };
+        let name = |day: u32| WEEKDAYS[day as usize - 1];
+        let ranges: Vec<String> = days
+            .runs()
+            .into_iter()
+            .map(|(first, last)| match last - first {
+                0 => name(first).to_string(),
+                1 => format!("{},{}", name(first), name(last)),
+                _ => format!("{}..{}", name(first), name(last)),
+            })
+            .collect();
+        format!("{} ", ranges.join(","))
+    };
+
+    Ok(Some(format!(
+        "{}{}-{}-{} {}:{}:{} {}",
+        weekdays,
+        Field::of(cron.years(), 1970, 2100).systemd(4),
+        Field::of(cron.months(), 1, 12).systemd(2),
+        Field::of(cron.days_of_month(), 1, 31).systemd(2),
+        Field::of(cron.hours(), 0, 23).systemd(2),
+        Field::of(cron.minutes(), 0, 59).systemd(2),
+        Field::of(cron.seconds(), 0, 59).systemd(2),
+        schedule.timezone().name()
+    )))
+}
+
+/// The five crontab fields of a schedule (or `@reboot`).
+pub fn crontab_schedule(schedule: &TaskSchedule) -> Result<String> {
+    let Some(cron) = schedule.cron() else {
+        return Ok("@reboot".to_string());
+    };
+
+    let seconds = Field::of(cron.seconds(), 0, 59);
+    if seconds.values != [0] {
+        return Err(anyhow::anyhow!(
+            "it runs at seconds other than :00, but crontab schedules are per minute"
+        ));
+    }
+    if !Field::of(cron.years(), 1970, 2100).is_all() {
+        return Err(anyhow::anyhow!("crontab schedules can't restrict the year"));
+    }
+
+    let days_of_month = Field::of(cron.days_of_month(), 1, 31);
+    let days_of_week = Field::of(cron.days_of_week(), 1, 7);
+    if !days_of_month.is_all() && !days_of_week.is_all() {
+        return Err(anyhow::anyhow!(
+            "it restricts both day of month and day of week, which cron would run \
+             when either matches rather than both"
+        ));
+    }
+    // Crontab counts days of the week 0-6 from Sunday
+    let days_of_week = Field {
+        values: days_of_week.values.iter().map(|day| day - 1).collect(),
+        min: 0,
+        max: 6,
+    };
+
+    Ok(format!(
+        "{} {} {} {} {}",
+        Field::of(cron.minutes(), 0, 59).crontab(),
+        Field::of(cron.hours(), 0, 23).crontab(),
+        days_of_month.crontab(),
+        Field::of(cron.months(), 1, 12).crontab(),
+        days_of_week.crontab()
+    ))
+}
+
+/// A task's command as it goes on a crontab line, run by cron's `/bin/sh`.
+fn crontab_command(event: &Event) -> Result<String> {
+    let command = match (&event.shell, event.timeout) {
+        // Shell scripts for the default shell go in as written
+        (Some(shell), None) if shell == DEFAULT_SHELL => event.command.clone(),
+        _ => {
+            let (program, args) = event.program_and_args()?;
+            let mut words: Vec<String> = Vec::new();
+            if let Some(timeout) = event.timeout {
+                words.extend(["timeout".to_string(), timeout.to_string()]);
+            }
+            words.extend(
+                std::iter::once(&program)
+                    .chain(&args)
+                    .map(|arg| shell_quote(arg)),
+            );
+            words.join(" ")
+        }
+    };
+
+    if command.contains('\n') {
+        return Err(anyhow::anyhow!(
+            "its command spans several lines, which a crontab line can't hold"
+        ));
+    }
+    // An unescaped `%` would start the command's stdin
+    Ok(command.replace('%', "\\%"))
+}
+
+/// Run `translate` on every task, reporting all failures at once.
+fn translate_all<T>(events: &[Event], translate: impl Fn(&Event) -> Result<T>) -> Result<Vec<T>> {
+    let mut translated = Vec::new();
+    let mut errors = Vec::new();
+    for event in events {
+        match translate(event) {
+            Ok(item) => translated.push(item),
+            Err(e) => errors.push(format!("  {}: {:#}", event.slug, e)),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Cannot export {} task(s):\n{}",
+            errors.len(),
+            errors.join("\n")
+        ));
+    }
+    Ok(translated)
+}
+
+/// The values a cron field matches, within its range.
+struct Field {
+    values: Vec<u32>,
+    min: u32,
+    max: u32,
+}
+
+impl Field {
+    fn of(spec: &impl TimeUnitSpec, min: u32, max: u32) -> Self {
+        Field {
+            values: spec.iter().collect(),
+            min,
+            max,
+        }
+    }
+
+    fn is_all(&self) -> bool {
+        self.values.len() as u32 == self.max - self.min + 1
+    }
+
+    /// `(start, step)` if three or more values repeat from `start` to the end
+    /// of the range.
+    fn step(&self) -> Option<(u32, u32)> {
+        let [first, second, _, ..] = self.values[..] else {
+            return None;
+        };
+        let step = second - first;
+        let last = *self.values.last()?;
+        let repeats = step > 1
+            && self.values.windows(2).all(|pair| pair[1] - pair[0] == step)
+            && last + step > self.max;
+        repeats.then_some((first, step))
+    }
+
+    /// Runs of consecutive values, as `(first, last)`.
+    fn runs(&self) -> Vec<(u32, u32)> {
+        let mut runs: Vec<(u32, u32)> = Vec::new();
+        for &value in &self.values {
+            match runs.last_mut() {
+                Some((_, last)) if *last + 1 == value => *last = value,
+                _ => runs.push((value, value)),
+            }
+        }
+        runs
+    }
+
+    fn systemd(&self, width: usize) -> String {
+        if self.is_all() {
+            return "*".to_string();
+        }
+        if let Some((start, step)) = self.step() {
+            return format!("{start:0width$}/{step}");
+        }
+
+        let ranges: Vec<String> = self
+            .runs()
+            .into_iter()
+            .map(|(first, last)| match last - first {
+                0 => format!("{first:0width$}"),
+                1 => format!("{first:0width$},{last:0width$}"),
+                _ => format!("{first:0width$}..{last:0width$}"),
+            })
+            .collect();
+        ranges.join(",")
+    }
+
+    fn crontab(&self) -> String {
+        if self.is_all() {
+            return "*".to_string();
+        }
+        match self.step() {
+            Some((start, step)) if start == self.min => return format!("*/{step}"),
+            Some((start, step)) => return format!("{}-{}/{}", start, self.max, step),
+            None => {}
+        }
+
+        let ranges: Vec<String> = self
+            .runs()
+            .into_iter()
+            .map(|(first, last)| match last - first {
+                0 => first.to_string(),
+                1 => format!("{first},{last}"),
+                _ => format!("{first}-{last}"),
+            })
+            .collect();
+        ranges.join(",")
+    }
+}
+
+/// Quote a word for a systemd `ExecStart=` line; `%` and `$` are doubled so
+/// systemd doesn't expand them.
+fn systemd_quote(word: &str) -> String {
+    let doubled = word.replace('%', "%%").replace('$', "$$");
+    if is_plain(word, "%$") {
+        return doubled;
+    }
+    let escaped = doubled
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+/// Quote a word for `/bin/sh`.
+fn shell_quote(word: &str) -> String {
+    if is_plain(word, "%") {
+        return word.to_string();
+    }
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+/// Whether `word` needs no quoting: letters, digits, a few punctuation
+/// characters that are never special, and `extra`.
+fn is_plain(word: &str, extra: &str) -> bool {
+    !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@,+".contains(c) || extra.contains(c))
+}
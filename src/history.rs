@@ -0,0 +1,155 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::backend::{Backend, StorageBackend};
+use crate::paths;
+
+/// Maximum number of bytes kept from each of stdout and stderr per run.
+pub const MAX_OUTPUT_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Running,
+    Succeeded,
+    Failed,
+    Killed,
+    TimedOut,
+    Cancelled,
+    /// Still running when the daemon shut down and its drain timeout ran out.
+    Interrupted,
+}
+
+impl std::fmt::Display for RunStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            RunStatus::Running => "running",
+            RunStatus::Succeeded => "succeeded",
+            RunStatus::Failed => "failed",
+            RunStatus::Killed => "killed",
+            RunStatus::TimedOut => "timed_out",
+            RunStatus::Cancelled => "cancelled",
+            RunStatus::Interrupted => "interrupted",
+        };
+        f.pad(name)
+    }
+}
+
+/// A single execution of a task.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub id: u64,
+    pub slug: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub duration_ms: Option<u64>,
+    pub status: RunStatus,
+    pub exit_code: Option<i32>,
+    /// Signal that terminated the process, if it did not exit on its own.
+    pub signal: Option<i32>,
+    /// Set when the command could not be started at all.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub stdout: String,
+    pub stderr: String,
+    /// True when stdout or stderr was cut down to `MAX_OUTPUT_BYTES`.
+    #[serde(default)]
+    pub output_truncated: bool,
+}
+
+impl RunRecord {
+    /// A fresh record for a run that is just starting.
+    pub fn started(slug: &str, started_at: DateTime<Utc>) -> Self {
+        RunRecord {
+            id: 0,
+            slug: slug.to_string(),
+            started_at,
+            finished_at: None,
+            duration_ms: None,
+            status: RunStatus::Running,
+            exit_code: None,
+            signal: None,
+            error: None,
+            stdout: String::new(),
+            stderr: String::new(),
+            output_truncated: false,
+        }
+    }
+}
+
+/// Run records of one task, oldest first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    pub runs: Vec<RunRecord>,
+}
+
+impl History {
+    pub async fn load(slug: &str) -> Result<Self> {
+        Backend::open().await?.load_history(slug).await
+    }
+
+    /// Append a run to the task's history, keeping at most `limit` records.
+    // The runner records through the scheduler's backend; this and `update`
+    // are for library users and tests
+    #[allow(dead_code)]
+    pub async fn record(run: RunRecord, limit: usize) -> Result<RunRecord> {
+        Backend::open().await?.record_run(run, limit).await
+    }
+
+    /// Replace a previously recorded run (matched by id) with its final state.
+    #[allow(dead_code)]
+    pub async fn update(run: &RunRecord) -> Result<()> {
+        Backend::open().await?.update_run(run).await
+    }
+
+    pub async fn remove(slug: &str) -> Result<()> {
+        Backend::open().await?.remove_history(slug).await
+    }
+
+    pub fn latest(&self) -> Option<&RunRecord> {
+        self.runs.last()
+    }
+
+    pub fn get(&self, id: u64) -> Option<&RunRecord> {
+        self.runs.iter().find(|r| r.id == id)
+    }
+
+    /// File that receives a run's combined output while it is still running.
+    pub fn live_log_path(slug: &str, id: u64) -> Result<PathBuf> {
+        Ok(paths::state_dir()?
+            .join("history")
+            .join(format!("{}.{id}.log", file_name(slug))))
+    }
+
+    pub fn next_id(&self) -> u64 {
+        self.runs.iter().map(|r| r.id).max().map_or(1, |id| id + 1)
+    }
+
+    pub(crate) fn prune(&mut self, limit: usize) {
+        if self.runs.len() > limit {
+            let excess = self.runs.len() - limit;
+            self.runs.drain(..excess);
+        }
+    }
+}
+
+/// Name under which a task's history is stored.
+///
+/// Slugs are free-form, keep them from escaping the history directory.
+pub(crate) fn file_name(slug: &str) -> String {
+    slug.replace(['/', '\\'], "_")
+}
+
+/// Decode captured output, keeping only the last `MAX_OUTPUT_BYTES` bytes.
+///
+/// The tail is kept because that is where errors usually end up.
+pub fn truncate_output(bytes: &[u8]) -> (String, bool) {
+    if bytes.len() <= MAX_OUTPUT_BYTES {
+        return (String::from_utf8_lossy(bytes).into_owned(), false);
+    }
+
+    let tail = &bytes[bytes.len() - MAX_OUTPUT_BYTES..];
+    (String::from_utf8_lossy(tail).into_owned(), true)
+}
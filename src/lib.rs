@@ -1,7 +1,20 @@
+pub mod backend;
 pub mod cli;
+pub mod config;
+pub mod control;
+pub mod crontab;
 pub mod daemon;
+pub mod export;
+pub mod history;
+pub mod migrations;
+pub mod paths;
+pub mod runner;
+pub mod schedule;
 pub mod scheduler;
+pub mod sd_notify;
+pub mod state;
 pub mod storage;
+pub mod taskfile;
 pub mod tui;
 
 pub use scheduler::Scheduler;
@@ -12,6 +25,21 @@ mod tests {
     use std::env;
     use tempfile::TempDir;
 
+    // Tests share one process environment, so the ones that point
+    // SINGLESCHEDULE_HOME at a temp dir take turns.
+    static TEST_HOME_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    async fn test_home() -> (TempDir, tokio::sync::MutexGuard<'static, ()>) {
+        let guard = TEST_HOME_LOCK.lock().await;
+        let temp_dir = TempDir::new().unwrap();
+        unsafe {
+            env::set_var(paths::HOME_ENV, temp_dir.path());
+            env::set_var(daemon::NO_DAEMON_ENV, "1");
+            env::remove_var(paths::PROFILE_ENV);
+        }
+        (temp_dir, guard)
+    }
+
     #[tokio::test]
     async fn test_storage_new() {
         let storage = storage::Storage::new();
@@ -20,10 +48,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_storage_save_and_load() {
-        let temp_dir = TempDir::new().unwrap();
-        unsafe {
-            env::set_var("SINGLESCHEDULE_TEST_HOME", temp_dir.path());
-        }
+        let (_temp_dir, _guard) = test_home().await;
 
         let mut storage = storage::Storage::new();
         let event = storage::Event {
@@ -34,6 +59,7 @@ mod tests {
             created_at: chrono::Utc::now(),
             last_run: None,
             active: true,
+            ..Default::default()
         };
 
         storage.events.push(event.clone());
@@ -47,12 +73,833 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_add_duplicate_slug() {
+    async fn test_storage_falls_back_to_backup_when_corrupt() {
+        let (temp_dir, _guard) = test_home().await;
+        let dir = temp_dir.path();
+
+        let mut storage = storage::Storage::new();
+        for slug in ["first", "second"] {
+            storage.events.push(storage::Event {
+                slug: slug.to_string(),
+                cron: "0 * * * * *".to_string(),
+                command: "echo hello".to_string(),
+                ..Default::default()
+            });
+            storage.save().await.unwrap();
+        }
+
+        // The backup holds the previous save and no temp files are left behind
+        let backup = std::fs::read_to_string(dir.join("events.json.bak")).unwrap();
+        assert!(backup.contains("first") && !backup.contains("second"));
+        let leftovers: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".tmp"))
+            .collect();
+        assert!(leftovers.is_empty());
+
+        // A torn write is survived by loading the backup
+        std::fs::write(dir.join("events.json"), "{\"events\": [").unwrap();
+        let loaded = storage::Storage::load().await.unwrap();
+        assert_eq!(loaded.events.len(), 1);
+        assert_eq!(loaded.events[0].slug, "first");
+
+        // Saving over the corrupt file keeps the good backup
+        loaded.save().await.unwrap();
+        let backup = std::fs::read_to_string(dir.join("events.json.bak")).unwrap();
+        assert!(backup.contains("first"));
+
+        std::fs::write(dir.join("events.json"), "garbage").unwrap();
+        std::fs::write(dir.join("events.json.bak"), "garbage").unwrap();
+        assert!(storage::Storage::load().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_state_is_kept_aside() {
+        let (temp_dir, _guard) = test_home().await;
+        let dir = temp_dir.path();
+
+        // Starts over with empty state, keeping the unreadable file
+        std::fs::write(dir.join("state.json"), "{\"tasks\": {").unwrap();
+        let loaded = state::State::load().await.unwrap();
+        assert!(loaded.tasks.is_empty());
+        assert!(!dir.join("state.json").exists());
+
+        state::State::update(|state| {
+            state.tasks.insert("fresh".to_string(), Default::default());
+            Ok(())
+        })
+        .await
+        .unwrap();
+        assert!(state::State::load()
+            .await
+            .unwrap()
+            .tasks
+            .contains_key("fresh"));
+        assert_eq!(
+            std::fs::read_to_string(dir.join("state.json.corrupt")).unwrap(),
+            "{\"tasks\": {"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_storage_updates_do_not_lose_concurrent_edits() {
+        let (_temp_dir, _guard) = test_home().await;
+
+        let updates = (0..20).map(|i| {
+            tokio::spawn(storage::Storage::update(move |storage| {
+                storage.insert_event(storage::Event {
+                    slug: format!("task-{i}"),
+                    cron: "0 * * * * *".to_string(),
+                    command: "echo hello".to_string(),
+                    ..Default::default()
+                })
+            }))
+        });
+        for update in updates.collect::<Vec<_>>() {
+            update.await.unwrap().unwrap();
+        }
+
+        let loaded = storage::Storage::load().await.unwrap();
+        assert_eq!(loaded.events.len(), 20);
+
+        // A failed change leaves the file alone
+        let result = storage::Storage::update(|storage| {
+            storage.events.clear();
+            storage.insert_event(storage::Event::default())?;
+            storage.insert_event(storage::Event::default())
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(storage::Storage::load().await.unwrap().events.len(), 20);
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_keeps_tasks_added_during_a_run() {
+        let (temp_dir, _guard) = test_home().await;
+
+        let mut storage = storage::Storage::new();
+        storage.events.push(storage::Event {
+            slug: "long-run".to_string(),
+            // Missed its yearly slot, so it runs once at startup
+            cron: "0 0 0 1 1 *".to_string(),
+            command: "sleep 1".to_string(),
+            created_at: chrono::Utc::now() - chrono::Duration::days(400),
+            ..Default::default()
+        });
+        storage.save().await.unwrap();
+
+        let mut scheduler = Scheduler::new();
+        scheduler.load_events().await.unwrap();
+
+        let add_while_running = async {
+            tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+            storage::Storage::update(|storage| {
+                storage.insert_event(storage::Event {
+                    slug: "added-later".to_string(),
+                    cron: "0 0 0 1 1 *".to_string(),
+                    command: "echo later".to_string(),
+                    ..Default::default()
+                })
+            })
+            .await
+            .unwrap();
+        };
+        let run = tokio::time::timeout(std::time::Duration::from_millis(1800), scheduler.run());
+        let _ = tokio::join!(run, add_while_running);
+
+        let loaded = storage::Storage::load().await.unwrap();
+        let slugs: Vec<&str> = loaded.events.iter().map(|e| e.slug.as_str()).collect();
+        assert_eq!(slugs, vec!["long-run", "added-later"]);
+        assert!(loaded.events[0].last_run.is_some());
+        assert!(loaded.events[0].pid.is_none());
+
+        // Runtime fields only ever land in the state file
+        let dir = temp_dir.path();
+        let definitions = std::fs::read_to_string(dir.join("events.json")).unwrap();
+        assert!(!definitions.contains("last_run"));
+        let state = state::State::load().await.unwrap();
+        let task = &state.tasks["long-run"];
+        assert_eq!(task.last_run, loaded.events[0].last_run);
+        assert!(task.next_run.is_some());
+        assert_eq!(task.consecutive_failures, 0);
+    }
+
+    #[tokio::test]
+    async fn test_inline_runtime_state_is_migrated() {
+        let (temp_dir, _guard) = test_home().await;
+        let dir = temp_dir.path();
+        std::fs::create_dir_all(dir).unwrap();
+
+        std::fs::write(
+            dir.join("events.json"),
+            r#"{"events": [{
+                "slug": "old-task",
+                "cron": "0 * * * * *",
+                "command": "echo old",
+                "pid": 4242,
+                "created_at": "2025-01-25T12:00:00Z",
+                "last_run": "2025-01-25T12:01:00Z",
+                "active": true
+            }]}"#,
+        )
+        .unwrap();
+
+        let loaded = storage::Storage::load().await.unwrap();
+        let last_run = "2025-01-25T12:01:00Z".parse().ok();
+        assert_eq!(loaded.events[0].last_run, last_run);
+        assert_eq!(loaded.events[0].pid, None);
+
+        let definitions = std::fs::read_to_string(dir.join("events.json")).unwrap();
+        assert!(!definitions.contains("last_run") && !definitions.contains("pid"));
+        assert!(definitions.contains("old-task"));
+        let doc: serde_json::Value = serde_json::from_str(&definitions).unwrap();
+        assert_eq!(doc["version"], migrations::SCHEMA_VERSION);
+        let state = state::State::load().await.unwrap();
+        assert_eq!(state.tasks["old-task"].last_run, last_run);
+
+        // Edits to definitions keep the runtime state
+        storage::Storage::update(|storage| {
+            storage.events[0].active = false;
+            Ok(())
+        })
+        .await
+        .unwrap();
+        let loaded = storage::Storage::load().await.unwrap();
+        assert!(!loaded.events[0].active);
+        assert_eq!(loaded.events[0].last_run, last_run);
+
+        // An older release rewriting the file behind an open backend's back
+        // doesn't lose its runtime state either
+        let backend = backend::Backend::open().await.unwrap();
+        std::fs::write(
+            dir.join("events.json"),
+            r#"{"events": [{
+                "slug": "old-task",
+                "cron": "0 * * * * *",
+                "command": "echo old",
+                "created_at": "2025-01-25T12:00:00Z",
+                "last_run": "2025-01-25T13:01:00Z",
+                "active": true
+            }]}"#,
+        )
+        .unwrap();
+        let last_run = "2025-01-25T13:01:00Z".parse().ok();
+        let loaded = storage::Storage::load_from(&backend).await.unwrap();
+        assert_eq!(loaded.events[0].last_run, last_run);
+        let state = state::State::load().await.unwrap();
+        assert_eq!(state.tasks["old-task"].last_run, last_run);
+        let definitions = std::fs::read_to_string(dir.join("events.json")).unwrap();
+        assert!(!definitions.contains("last_run"));
+    }
+
+    #[test]
+    fn test_migration_v0_to_v1_makes_active_explicit() {
+        let mut doc = serde_json::json!({"events": [
+            {"slug": "old", "cron": "@daily", "command": "true"},
+            {"slug": "paused", "cron": "@daily", "command": "true", "active": false}
+        ]});
+        let mut state = state::State::default();
+
+        migrations::v0_to_v1(&mut doc, &mut state).unwrap();
+        assert_eq!(doc["events"][0]["active"], true);
+        assert_eq!(doc["events"][1]["active"], false);
+        assert!(state.tasks.is_empty());
+    }
+
+    #[test]
+    fn test_migration_v1_to_v2_moves_runtime_state() {
+        let mut doc = serde_json::json!({"version": 1, "events": [
+            {"slug": "ran", "cron": "@daily", "command": "true", "active": true,
+             "pid": 4242, "last_run": "2025-01-25T12:01:00Z"},
+            {"slug": "never-ran", "cron": "@daily", "command": "true", "active": true,
+             "pid": null, "last_run": null}
+        ]});
+        let mut state = state::State::default();
+        let newer = "2025-01-26T00:00:00Z".parse().ok();
+        state.tasks.entry("ran".to_string()).or_default().last_run = newer;
+
+        migrations::v1_to_v2(&mut doc, &mut state).unwrap();
+        for event in doc["events"].as_array().unwrap() {
+            assert!(event.get("pid").is_none() && event.get("last_run").is_none());
+        }
+        // The more recent of the two records wins
+        assert_eq!(state.tasks["ran"].last_run, newer);
+        assert_eq!(state.tasks["ran"].pid, None);
+        assert!(!state.tasks.contains_key("never-ran"));
+    }
+
+    #[test]
+    fn test_migrate_upgrades_step_by_step() {
+        let mut doc = serde_json::json!({"events": [
+            {"slug": "old", "cron": "@daily", "command": "true",
+             "created_at": "2025-01-25T12:00:00Z", "last_run": "2025-01-25T12:01:00Z"}
+        ]});
+        let mut state = state::State::default();
+
+        assert_eq!(migrations::schema_version(&doc).unwrap(), 0);
+        migrations::migrate(&mut doc, &mut state).unwrap();
+        assert_eq!(doc["version"], migrations::SCHEMA_VERSION);
+        assert_eq!(doc["events"][0]["active"], true);
+        assert!(state.tasks["old"].last_run.is_some());
+
+        let storage: storage::Storage = serde_json::from_value(doc).unwrap();
+        assert_eq!(storage.events[0].slug, "old");
+
+        // Malformed documents are reported rather than half-migrated
+        let mut doc = serde_json::json!({"events": ["not a task"]});
+        assert!(migrations::migrate(&mut doc, &mut state).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_newer_schema_version_is_refused() {
+        let (temp_dir, _guard) = test_home().await;
+        let dir = temp_dir.path();
+        std::fs::create_dir_all(dir).unwrap();
+
+        let future = format!(
+            r#"{{"version": {}, "events": [], "labels": {{}}}}"#,
+            migrations::SCHEMA_VERSION + 1
+        );
+        std::fs::write(dir.join("events.json"), &future).unwrap();
+
+        let error = storage::Storage::load().await.unwrap_err().to_string();
+        assert!(error.contains("upgrade singleschedule"), "{}", error);
+        assert!(storage::Storage::update(|_| Ok(())).await.is_err());
+        // The file is left untouched
+        assert_eq!(
+            std::fs::read_to_string(dir.join("events.json")).unwrap(),
+            future
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_backend_round_trip() {
+        use backend::StorageBackend;
+
         let temp_dir = TempDir::new().unwrap();
+        let db = backend::SqliteBackend::new(temp_dir.path().join("test.db"));
+        db.upgrade().await.unwrap();
+
+        let mut storage = storage::Storage::new();
+        for slug in ["b-task", "a-task"] {
+            storage.events.push(storage::Event {
+                slug: slug.to_string(),
+                cron: "@daily".to_string(),
+                command: "true".to_string(),
+                ..Default::default()
+            });
+        }
+        db.save_tasks(&storage).await.unwrap();
+        let loaded = db.load_tasks().await.unwrap();
+        assert_eq!(loaded.events, storage.events);
+
+        let mut state = state::State::default();
+        state
+            .tasks
+            .entry("a-task".to_string())
+            .or_default()
+            .failures = 3;
+        db.save_state(&state).await.unwrap();
+        assert_eq!(db.load_state().await.unwrap().tasks, state.tasks);
+
+        // Appending assigns ids and prunes down to the limit
+        for _ in 0..5 {
+            db.record_run(sample_run("a-task"), 3).await.unwrap();
+        }
+        let history = db.load_history("a-task").await.unwrap();
+        let ids: Vec<u64> = history.runs.iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec![3, 4, 5]);
+
+        let mut run = history.runs[0].clone();
+        run.status = history::RunStatus::Failed;
+        db.update_run(&run).await.unwrap();
+        let history = db.load_history("a-task").await.unwrap();
+        assert_eq!(history.get(3).unwrap().status, history::RunStatus::Failed);
+        assert!(db.load_history("b-task").await.unwrap().runs.is_empty());
+
+        db.remove_history("a-task").await.unwrap();
+        assert!(db.load_history("a-task").await.unwrap().runs.is_empty());
+
+        // A database from a newer release is refused
+        let conn = rusqlite::Connection::open(temp_dir.path().join("test.db")).unwrap();
+        conn.pragma_update(None, "user_version", migrations::SCHEMA_VERSION + 1)
+            .unwrap();
+        let error = db.load_tasks().await.unwrap_err().to_string();
+        assert!(error.contains("upgrade singleschedule"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_copy_storage_between_backends() {
+        let (temp_dir, _guard) = test_home().await;
+
+        let mut storage = storage::Storage::new();
+        storage.events.push(storage::Event {
+            slug: "copied".to_string(),
+            cron: "@daily".to_string(),
+            command: "echo copied".to_string(),
+            ..Default::default()
+        });
+        storage.save().await.unwrap();
+        history::History::record(sample_run("copied"), 10)
+            .await
+            .unwrap();
+        state::State::update(|state| {
+            state
+                .tasks
+                .entry("copied".to_string())
+                .or_default()
+                .failures = 2;
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let json = backend::Backend::new(backend::BackendKind::Json).unwrap();
+        let sqlite = backend::Backend::new(backend::BackendKind::Sqlite).unwrap();
+        assert_eq!(backend::copy_storage(&json, &sqlite).await.unwrap(), 1);
+        // The target now has tasks, so a second copy is refused
+        assert!(backend::copy_storage(&json, &sqlite).await.is_err());
+
+        // Switching the config over picks up everything from SQLite
+        let dir = temp_dir.path();
+        std::fs::write(dir.join("config.json"), r#"{"storage_backend": "sqlite"}"#).unwrap();
+        std::fs::remove_file(dir.join("events.json")).unwrap();
+
+        let loaded = storage::Storage::load().await.unwrap();
+        assert_eq!(loaded.events.len(), 1);
+        assert_eq!(loaded.events[0].command, "echo copied");
+        assert_eq!(
+            state::State::load().await.unwrap().tasks["copied"].failures,
+            2
+        );
+        let history = history::History::load("copied").await.unwrap();
+        assert_eq!(history.runs.len(), 1);
+
+        storage::Storage::update(|storage| {
+            storage.insert_event(storage::Event {
+                slug: "new".to_string(),
+                cron: "@hourly".to_string(),
+                command: "true".to_string(),
+                ..Default::default()
+            })
+        })
+        .await
+        .unwrap();
+        assert_eq!(storage::Storage::load().await.unwrap().events.len(), 2);
+        assert!(!dir.join("events.json").exists());
+    }
+
+    #[tokio::test]
+    async fn test_data_dir_resolution() {
+        let (temp_dir, _guard) = test_home().await;
+        let root = temp_dir.path();
+
+        let dirs = paths::Dirs::resolve().unwrap();
+        assert_eq!(dirs.config, root);
+        assert_eq!(dirs.state, root);
+
+        // --data-dir is made absolute for the daemon, which changes directory
+        paths::init(Some(std::path::Path::new("relative-dir")), None).unwrap();
+        let expected = env::current_dir().unwrap().join("relative-dir");
+        assert_eq!(paths::config_dir().unwrap(), expected);
+
+        let saved: Vec<_> = ["HOME", "XDG_CONFIG_HOME", "XDG_STATE_HOME"]
+            .into_iter()
+            .map(|name| (name, env::var_os(name)))
+            .collect();
+        unsafe {
+            env::remove_var(paths::HOME_ENV);
+            env::set_var("HOME", root.join("home"));
+            env::set_var("XDG_CONFIG_HOME", root.join("config"));
+            env::set_var("XDG_STATE_HOME", root.join("state"));
+        }
+
+        let xdg = paths::Dirs {
+            config: root.join("config/singleschedule"),
+            state: root.join("state/singleschedule"),
+        };
+        let resolved = paths::Dirs::resolve().unwrap();
+
+        // An existing legacy directory keeps being used...
+        std::fs::create_dir_all(root.join("home/.singleschedule")).unwrap();
+        let legacy = paths::Dirs::resolve().unwrap();
+        // ...until the XDG one exists
+        std::fs::create_dir_all(&xdg.config).unwrap();
+        let migrated = paths::Dirs::resolve().unwrap();
+
         unsafe {
-            env::set_var("SINGLESCHEDULE_TEST_HOME", temp_dir.path());
+            for (name, value) in saved {
+                match value {
+                    Some(value) => env::set_var(name, value),
+                    None => env::remove_var(name),
+                }
+            }
+        }
+
+        assert_eq!(resolved, xdg);
+        assert_eq!(legacy.config, root.join("home/.singleschedule"));
+        assert_eq!(legacy.state, legacy.config);
+        assert_eq!(migrated, xdg);
+    }
+
+    #[tokio::test]
+    async fn test_profiles_are_isolated() {
+        let (temp_dir, _guard) = test_home().await;
+
+        unsafe {
+            env::set_var(paths::PROFILE_ENV, "ops");
+        }
+        storage::Storage::update(|storage| {
+            storage.insert_event(storage::Event {
+                slug: "backup".to_string(),
+                cron: "@daily".to_string(),
+                command: "true".to_string(),
+                ..Default::default()
+            })
+        })
+        .await
+        .unwrap();
+        history::History::record(sample_run("backup"), 10)
+            .await
+            .unwrap();
+        let ops = paths::Dirs::resolve().unwrap();
+        unsafe {
+            env::remove_var(paths::PROFILE_ENV);
+        }
+
+        assert_eq!(ops.config, temp_dir.path().join("profiles/ops"));
+        assert!(ops.config.join("events.json").exists());
+        // The default profile sees none of it
+        assert!(storage::Storage::load().await.unwrap().events.is_empty());
+        assert!(history::History::load("backup")
+            .await
+            .unwrap()
+            .runs
+            .is_empty());
+
+        let base = paths::Dirs::base().unwrap();
+        assert_eq!(base.profiles().unwrap(), vec!["default", "ops"]);
+        let backend = backend::Backend::open_in(&base.profile("ops"))
+            .await
+            .unwrap();
+        let tasks = storage::Storage::load_from(&backend).await.unwrap();
+        assert_eq!(tasks.events[0].slug, "backup");
+        cli::handle_list(true).await.unwrap();
+
+        unsafe {
+            env::set_var(paths::PROFILE_ENV, "../escape");
+        }
+        assert!(storage::Storage::load().await.is_err());
+        unsafe {
+            env::remove_var(paths::PROFILE_ENV);
+        }
+    }
+
+    #[test]
+    fn test_task_file_plan_and_apply() {
+        use taskfile::{Change, TaskFile};
+
+        let mut storage = storage::Storage::new();
+        for (slug, cron) in [("backup", "@daily"), ("cleanup", "@hourly")] {
+            storage
+                .insert_event(storage::Event {
+                    slug: slug.to_string(),
+                    cron: cron.to_string(),
+                    command: "true".to_string(),
+                    argv: Some(vec!["true".to_string()]),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        storage.events[0].active = false;
+        let created_at = storage.events[0].created_at;
+
+        let file = TaskFile::parse(
+            r#"
+            [[task]]
+            slug = "backup"
+            cron = "0 0 3 * * *"
+            command = ["true"]
+            timeout = 60
+
+            [[task]]
+            slug = "report"
+            cron = "@weekly"
+            command = "make report | mail ops"
+            concurrency_policy = "queue"
+            "#,
+        )
+        .unwrap();
+
+        let plan = file.plan(&storage, false).unwrap();
+        assert_eq!(plan.kept, vec!["cleanup"]);
+        assert_eq!(plan.changes.len(), 2);
+        let Change::Update { fields, .. } = &plan.changes[0] else {
+            panic!("expected an update, got {:?}", plan.changes[0]);
+        };
+        let changed: Vec<&str> = fields.iter().map(|f| f.field.as_str()).collect();
+        assert_eq!(changed, vec!["cron", "timeout"]);
+        let Change::Add(report) = &plan.changes[1] else {
+            panic!("expected an add, got {:?}", plan.changes[1]);
+        };
+        assert_eq!(report.shell.as_deref(), Some(storage::DEFAULT_SHELL));
+        assert_eq!(report.concurrency_policy, storage::ConcurrencyPolicy::Queue);
+
+        plan.apply(&mut storage);
+        let backup = &storage.events[0];
+        assert_eq!(backup.cron, "0 0 3 * * *");
+        assert_eq!(backup.timeout, Some(60));
+        // Not part of the file, so left alone
+        assert!(!backup.active);
+        assert_eq!(backup.created_at, created_at);
+        assert!(file.plan(&storage, false).unwrap().changes.is_empty());
+
+        let plan = file.plan(&storage, true).unwrap();
+        assert!(plan.kept.is_empty());
+        assert_eq!(plan.changes, vec![Change::Remove("cleanup".to_string())]);
+        plan.apply(&mut storage);
+        let slugs: Vec<&str> = storage.events.iter().map(|e| e.slug.as_str()).collect();
+        assert_eq!(slugs, vec!["backup", "report"]);
+    }
+
+    #[test]
+    fn test_task_file_export_round_trip() {
+        use taskfile::TaskFile;
+
+        let mut storage = storage::Storage::new();
+        storage.events = vec![
+            storage::Event {
+                slug: "argv".to_string(),
+                cron: "*/5 * * * *".to_string(),
+                command: "echo hello world".to_string(),
+                argv: Some(vec!["echo".to_string(), "hello world".to_string()]),
+                timezone: Some("Europe/Berlin".to_string()),
+                misfire_policy: storage::MisfirePolicy::SkipToNext,
+                active: false,
+                ..Default::default()
+            },
+            storage::Event {
+                slug: "shell".to_string(),
+                cron: "@daily".to_string(),
+                command: "cd /tmp && ls".to_string(),
+                shell: Some("/bin/bash".to_string()),
+                history_limit: Some(5),
+                ..Default::default()
+            },
+            // Written before argv existed
+            storage::Event {
+                slug: "legacy".to_string(),
+                cron: "@hourly".to_string(),
+                command: "echo legacy".to_string(),
+                ..Default::default()
+            },
+        ];
+
+        let exported = TaskFile::from_storage(&storage).to_toml().unwrap();
+        assert!(exported.contains("[[task]]"));
+        let file = TaskFile::parse(&exported).unwrap();
+        assert!(file.plan(&storage, true).unwrap().changes.is_empty());
+
+        let mut copy = storage::Storage::new();
+        file.plan(&copy, true).unwrap().apply(&mut copy);
+        for (copied, original) in copy.events.iter().zip(&storage.events) {
+            assert_eq!(copied.slug, original.slug);
+            assert_eq!(copied.active, original.active);
+            assert_eq!(
+                copied.program_and_args().unwrap(),
+                original.program_and_args().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_task_file_validation() {
+        use taskfile::TaskFile;
+
+        let duplicate = r#"
+            [[task]]
+            slug = "a"
+            cron = "@daily"
+            command = "true"
+
+            [[task]]
+            slug = "a"
+            cron = "@hourly"
+            command = "true"
+        "#;
+        assert!(TaskFile::parse(duplicate).is_err());
+
+        let bad_cron = "[[task]]\nslug = \"a\"\ncron = \"not a cron\"\ncommand = \"true\"\n";
+        assert!(TaskFile::parse(bad_cron).is_err());
+
+        let empty_command = "[[task]]\nslug = \"a\"\ncron = \"@daily\"\ncommand = []\n";
+        assert!(TaskFile::parse(empty_command).is_err());
+
+        let unknown_field =
+            "[[task]]\nslug = \"a\"\ncron = \"@daily\"\ncommand = \"true\"\ncolour = \"red\"\n";
+        assert!(TaskFile::parse(unknown_field).is_err());
+    }
+
+    #[test]
+    fn test_crontab_import() {
+        let content = r#"
+# m h dom mon dow command
+SHELL=/bin/bash
+MAILTO=ops@example.com
+PATH = "/usr/local/bin:/usr/bin:/bin"
+0 2 * * * /home/user/bin/backup.sh --full  # Nightly backup
+*/5 * * * * echo "a # b" >> /tmp/log
+CRON_TZ=Europe/Berlin
+@daily mail -s report ops%Hello,%100\% done%
+61 * * * * /bin/true
+0 3 * * 1 /home/user/bin/backup.sh
+0 4 * * * cleanup.sh # existing
+0 5 * * * wc -l %one%two # count lines
+"#;
+        let taken = ["backup".to_string(), "existing".to_string()].into();
+        let import = crontab::parse(content, &taken);
+
+        let slugs: Vec<&str> = import.events.iter().map(|e| e.slug.as_str()).collect();
+        assert_eq!(
+            slugs,
+            vec!["nightly-backup", "echo", "mail", "backup-2", "count-lines"]
+        );
+
+        let backup = &import.events[0];
+        assert_eq!(backup.cron, "0 2 * * *");
+        assert_eq!(backup.shell.as_deref(), Some("/bin/bash"));
+        assert_eq!(backup.timezone, None);
+        assert_eq!(
+            backup.command,
+            "export PATH='/usr/local/bin:/usr/bin:/bin'; /home/user/bin/backup.sh --full"
+        );
+        // A quoted `#` is not a comment
+        assert!(import.events[1]
+            .command
+            .ends_with(r#"echo "a # b" >> /tmp/log"#));
+
+        let mail = &import.events[2];
+        assert_eq!(mail.timezone.as_deref(), Some("Europe/Berlin"));
+        assert!(mail
+            .command
+            .ends_with("printf '%s\\n' 'Hello,' '100% done' | ( mail -s report ops )"));
+
+        // Named by the comment after its input
+        assert!(import.events[4]
+            .command
+            .ends_with("printf '%s\\n' 'one' 'two' | ( wc -l )"));
+
+        let skipped: Vec<usize> = import.skipped.iter().map(|s| s.line).collect();
+        assert_eq!(skipped, vec![4, 10, 12]);
+        assert!(import.skipped[2].reason.contains("already exists"));
+    }
+
+    #[test]
+    fn test_export_schedules_to_systemd_and_crontab() {
+        let cases = [
+            ("*/5 * * * *", None, "*-*-* *:00/5:00 UTC", "*/5 * * * *"),
+            (
+                "0 30 9 * * MON-FRI",
+                Some("Europe/Berlin"),
+                "Mon..Fri *-*-* 09:30:00 Europe/Berlin",
+                "30 9 * * 1-5",
+            ),
+            ("@weekly", None, "Sun *-*-* 00:00:00 UTC", "0 0 * * 0"),
+            (
+                "0 0 6,18 1-15 1,7 *",
+                None,
+                "*-01,07-01..15 06,18:00:00 UTC",
+                "0 6,18 1-15 1,7 *",
+            ),
+            (
+                "0 15 10 * * SAT,SUN",
+                None,
+                "Sat,Sun *-*-* 10:15:00 UTC",
+                "15 10 * * 0,6",
+            ),
+        ];
+        for (cron, timezone, calendar, crontab) in cases {
+            let schedule = schedule::TaskSchedule::new(cron, timezone).unwrap();
+            assert_eq!(
+                export::on_calendar(&schedule).unwrap().as_deref(),
+                Some(calendar),
+                "{cron}"
+            );
+            assert_eq!(
+                export::crontab_schedule(&schedule).unwrap(),
+                crontab,
+                "{cron}"
+            );
+        }
+
+        let reboot = schedule::TaskSchedule::new("@reboot", None).unwrap();
+        assert_eq!(export::on_calendar(&reboot).unwrap(), None);
+        assert_eq!(export::crontab_schedule(&reboot).unwrap(), "@reboot");
+
+        // Cron has no seconds, and ORs day of month with day of week
+        for cron in ["*/10 * * * * *", "0 0 12 1 * MON", "0 0 0 1 1 * 2030"] {
+            let schedule = schedule::TaskSchedule::new(cron, None).unwrap();
+            assert!(export::on_calendar(&schedule).unwrap().is_some());
+            assert!(export::crontab_schedule(&schedule).is_err(), "{cron}");
         }
 
+        let events = vec![
+            storage::Event {
+                slug: "report".to_string(),
+                cron: "0 2 * * *".to_string(),
+                command: "echo 100% $HOME".to_string(),
+                argv: Some(vec!["echo".to_string(), "100% $HOME".to_string()]),
+                timeout: Some(60),
+                ..Default::default()
+            },
+            storage::Event {
+                slug: "paused".to_string(),
+                cron: "@daily".to_string(),
+                command: "make report | mail ops".to_string(),
+                shell: Some(storage::DEFAULT_SHELL.to_string()),
+                active: false,
+                ..Default::default()
+            },
+        ];
+
+        let units = export::systemd_units(&events).unwrap();
+        let names: Vec<&str> = units.iter().map(|u| u.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "singleschedule-report.service",
+                "singleschedule-report.timer",
+                "singleschedule-paused.service",
+                "singleschedule-paused.timer"
+            ]
+        );
+        assert!(units[0]
+            .contents
+            .contains("ExecStart=echo \"100%% $$HOME\"\nTimeoutStartSec=60\n"));
+        assert!(units[1]
+            .contents
+            .contains("OnCalendar=*-*-* 02:00:00 UTC\n"));
+        assert!(units[2]
+            .contents
+            .contains("ExecStart=/bin/sh -c \"make report | mail ops\"\n"));
+
+        let crontab = export::crontab(&events).unwrap();
+        assert!(
+            crontab.contains("CRON_TZ=UTC\n0 2 * * * timeout 60 echo '100\\% $HOME' # report\n")
+        );
+        assert!(crontab.contains("\n# 0 0 * * * make report | mail ops # paused\n"));
+
+        // The exported crontab imports back into the same tasks
+        let import = crontab::parse(&crontab, &Default::default());
+        let slugs: Vec<&str> = import.events.iter().map(|e| e.slug.as_str()).collect();
+        assert_eq!(slugs, vec!["report"]);
+    }
+
+    #[tokio::test]
+    async fn test_add_duplicate_slug() {
+        let (_temp_dir, _guard) = test_home().await;
+
         let mut storage = storage::Storage::new();
         let event = storage::Event {
             slug: "duplicate".to_string(),
@@ -62,6 +909,7 @@ mod tests {
             created_at: chrono::Utc::now(),
             last_run: None,
             active: true,
+            ..Default::default()
         };
 
         storage.events.push(event);
@@ -72,6 +920,7 @@ mod tests {
             "duplicate".to_string(),
             "0 * * * * *".to_string(),
             vec!["echo".to_string(), "test2".to_string()],
+            cli::AddOptions::default(),
         )
         .await;
 
@@ -86,10 +935,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_remove_nonexistent() {
-        let temp_dir = TempDir::new().unwrap();
-        unsafe {
-            env::set_var("SINGLESCHEDULE_TEST_HOME", temp_dir.path());
-        }
+        let (_temp_dir, _guard) = test_home().await;
 
         let result = cli::handle_remove("nonexistent".to_string()).await;
         assert!(result.is_err());
@@ -102,6 +948,7 @@ mod tests {
             "invalid-cron".to_string(),
             "invalid cron expression".to_string(),
             vec!["echo".to_string(), "test".to_string()],
+            cli::AddOptions::default(),
         )
         .await;
 
@@ -113,20 +960,870 @@ mod tests {
     }
 
     #[test]
-    fn test_schedule_parsing() {
-        use cron::Schedule;
-        use std::str::FromStr;
+    fn test_program_and_args_modes() {
+        // argv mode keeps quoted arguments intact
+        let event = storage::Event {
+            command: "echo hello world".to_string(),
+            argv: Some(vec!["echo".to_string(), "hello world".to_string()]),
+            ..Default::default()
+        };
+        let (program, args) = event.program_and_args().unwrap();
+        assert_eq!(program, "echo");
+        assert_eq!(args, vec!["hello world".to_string()]);
+
+        // shell mode hands the whole command line to the shell
+        let event = storage::Event {
+            command: "echo \"hello world\" | wc -w".to_string(),
+            shell: Some(storage::DEFAULT_SHELL.to_string()),
+            ..Default::default()
+        };
+        let (program, args) = event.program_and_args().unwrap();
+        assert_eq!(program, "/bin/sh");
+        assert_eq!(args, vec!["-c", "echo \"hello world\" | wc -w"]);
+
+        // legacy events without argv or shell still split on whitespace
+        let event = storage::Event {
+            command: "echo legacy task".to_string(),
+            ..Default::default()
+        };
+        let (program, args) = event.program_and_args().unwrap();
+        assert_eq!(program, "echo");
+        assert_eq!(args, vec!["legacy", "task"]);
+
+        let event = storage::Event::default();
+        assert!(event.program_and_args().is_err());
+    }
 
+    #[test]
+    fn test_legacy_event_deserialization() {
+        let json = r#"{
+            "slug": "old-task",
+            "cron": "0 * * * * *",
+            "command": "echo old",
+            "pid": null,
+            "created_at": "2025-01-25T12:00:00Z",
+            "last_run": null
+        }"#;
+
+        let event: storage::Event = serde_json::from_str(json).unwrap();
+        assert!(event.active);
+        assert!(event.argv.is_none());
+        assert!(event.shell.is_none());
+    }
+
+    fn sample_run(slug: &str) -> history::RunRecord {
+        let now = chrono::Utc::now();
+        history::RunRecord {
+            id: 0,
+            slug: slug.to_string(),
+            started_at: now,
+            finished_at: Some(now),
+            duration_ms: Some(0),
+            status: history::RunStatus::Succeeded,
+            exit_code: Some(0),
+            signal: None,
+            error: None,
+            stdout: "ok\n".to_string(),
+            stderr: String::new(),
+            output_truncated: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_history_record_and_retention() {
+        let (temp_dir, _guard) = test_home().await;
+
+        for _ in 0..5 {
+            history::History::record(sample_run("history-task"), 3)
+                .await
+                .unwrap();
+        }
+
+        let loaded = history::History::load("history-task").await.unwrap();
+        let ids: Vec<u64> = loaded.runs.iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec![3, 4, 5]);
+        assert_eq!(loaded.next_id(), 6);
+
+        // Written atomically, under a lock other processes take too
+        let dir = temp_dir.path();
+        assert!(dir.join("history.lock").exists());
+        let files: Vec<_> = std::fs::read_dir(dir.join("history"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(files, vec!["history-task.json"]);
+
+        history::History::remove("history-task").await.unwrap();
+        let loaded = history::History::load("history-task").await.unwrap();
+        assert!(loaded.runs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_runner_records_output_and_exit_status() {
+        let (_temp_dir, _guard) = test_home().await;
+        let backend = backend::Backend::open().await.unwrap();
+
+        let event = storage::Event {
+            slug: "runner-task".to_string(),
+            command: "echo out; echo err >&2; exit 3".to_string(),
+            shell: Some(storage::DEFAULT_SHELL.to_string()),
+            ..Default::default()
+        };
+
+        let record = runner::run(&backend, &event, &runner::RunControl::new(), |_| {}).await;
+        assert_eq!(record.status, history::RunStatus::Failed);
+        assert_eq!(record.exit_code, Some(3));
+        assert_eq!(record.stdout, "out\n");
+        assert_eq!(record.stderr, "err\n");
+        assert!(record.finished_at.is_some());
+
+        let loaded = history::History::load("runner-task").await.unwrap();
+        assert_eq!(loaded.latest(), Some(&record));
+        let live_log = history::History::live_log_path("runner-task", record.id).unwrap();
+        assert!(!live_log.exists());
+
+        // Quoted arguments reach the program as a single argument
+        let event = storage::Event {
+            slug: "runner-argv".to_string(),
+            command: "echo hello world".to_string(),
+            argv: Some(vec!["echo".to_string(), "hello  world".to_string()]),
+            ..Default::default()
+        };
+        let record = runner::run(&backend, &event, &runner::RunControl::new(), |_| {}).await;
+        assert_eq!(record.status, history::RunStatus::Succeeded);
+        assert_eq!(record.stdout, "hello  world\n");
+    }
+
+    #[tokio::test]
+    async fn test_runner_timeout_kills_process_group() {
+        let (_temp_dir, _guard) = test_home().await;
+        let backend = backend::Backend::open().await.unwrap();
+
+        // The background sleep keeps the pipe open unless the whole group dies
+        let event = storage::Event {
+            slug: "timeout-task".to_string(),
+            command: "sleep 30 & echo started; wait".to_string(),
+            shell: Some(storage::DEFAULT_SHELL.to_string()),
+            timeout: Some(1),
+            ..Default::default()
+        };
+
+        let started = std::time::Instant::now();
+        let record = runner::run(&backend, &event, &runner::RunControl::new(), |_| {}).await;
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+        assert_eq!(record.status, history::RunStatus::TimedOut);
+        assert_eq!(record.stdout, "started\n");
+        assert!(record.signal.is_some());
+
+        // The shell exits at once, but the sleep it leaves behind keeps the
+        // pipes open until the deadline kills the group
+        let event = storage::Event {
+            command: "sleep 30 & echo started".to_string(),
+            ..event
+        };
+        let started = std::time::Instant::now();
+        let record = runner::run(&backend, &event, &runner::RunControl::new(), |_| {}).await;
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+        assert_eq!(record.status, history::RunStatus::TimedOut);
+        assert_eq!(record.stdout, "started\n");
+        assert_eq!(record.exit_code, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_runs_due_tasks_concurrently() {
+        let (_temp_dir, _guard) = test_home().await;
+
+        let mut storage = storage::Storage::new();
+        for slug in ["slow-a", "slow-b"] {
+            storage.events.push(storage::Event {
+                slug: slug.to_string(),
+                // Missed its yearly slot, so it runs once at startup
+                cron: "0 0 0 1 1 *".to_string(),
+                created_at: chrono::Utc::now() - chrono::Duration::days(400),
+                command: "sleep 1".to_string(),
+                ..Default::default()
+            });
+        }
+        storage.save().await.unwrap();
+
+        let mut scheduler = Scheduler::new();
+        scheduler.load_events().await.unwrap();
+        let _ = tokio::time::timeout(std::time::Duration::from_millis(1800), scheduler.run()).await;
+
+        let a = history::History::load("slow-a").await.unwrap();
+        let b = history::History::load("slow-b").await.unwrap();
+        let (a, b) = (a.latest().unwrap(), b.latest().unwrap());
+        assert_eq!(a.status, history::RunStatus::Succeeded);
+        assert_eq!(b.status, history::RunStatus::Succeeded);
+
+        // Both started together instead of one waiting for the other
+        let gap = (a.started_at - b.started_at).num_milliseconds().abs();
+        assert!(gap < 500, "runs started {gap}ms apart");
+
+        // Completed runs are reported back and recorded as the last run
+        let loaded = storage::Storage::load().await.unwrap();
+        assert!(loaded.events.iter().all(|e| e.last_run.is_some()));
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_respects_concurrency_cap() {
+        let (_temp_dir, _guard) = test_home().await;
+
+        let mut storage = storage::Storage::new();
+        for slug in ["capped-a", "capped-b"] {
+            storage.events.push(storage::Event {
+                slug: slug.to_string(),
+                // Missed its yearly slot, so it runs once at startup
+                cron: "0 0 0 1 1 *".to_string(),
+                created_at: chrono::Utc::now() - chrono::Duration::days(400),
+                command: "sleep 1".to_string(),
+                ..Default::default()
+            });
+        }
+        storage.save().await.unwrap();
+
+        let config = config::Config {
+            max_concurrent_runs: 1,
+            ..Default::default()
+        };
+        let mut scheduler = Scheduler::with_config(&config);
+        scheduler.load_events().await.unwrap();
+        let _ = tokio::time::timeout(std::time::Duration::from_millis(2800), scheduler.run()).await;
+
+        let a = history::History::load("capped-a").await.unwrap();
+        let b = history::History::load("capped-b").await.unwrap();
+        let (a, b) = (a.latest().unwrap(), b.latest().unwrap());
+        assert_eq!(a.status, history::RunStatus::Succeeded);
+        assert_eq!(b.status, history::RunStatus::Succeeded);
+
+        let gap = (a.started_at - b.started_at).num_milliseconds().abs();
+        assert!(
+            gap >= 900,
+            "runs overlapped despite the cap ({gap}ms apart)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_drain_on_shutdown() {
+        let (_temp_dir, _guard) = test_home().await;
+
+        let mut storage = storage::Storage::new();
+        for (slug, command) in [
+            ("drain-quick", "sleep 1"),
+            ("drain-slow", "sleep 30"),
+            ("drain-pending", "true"),
+        ] {
+            storage.events.push(storage::Event {
+                slug: slug.to_string(),
+                // Missed its yearly slot, so it runs once at startup
+                cron: "0 0 0 1 1 *".to_string(),
+                created_at: chrono::Utc::now() - chrono::Duration::days(400),
+                command: command.to_string(),
+                ..Default::default()
+            });
+        }
+        storage.save().await.unwrap();
+
+        let config = config::Config {
+            max_concurrent_runs: 2,
+            ..Default::default()
+        };
+        let mut scheduler = Scheduler::with_config(&config);
+        scheduler.load_events().await.unwrap();
+        scheduler
+            .run_until(tokio::time::sleep(std::time::Duration::from_millis(500)))
+            .await
+            .unwrap();
+
+        let started = std::time::Instant::now();
+        scheduler.drain(std::time::Duration::from_secs(2)).await;
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+
+        let quick = history::History::load("drain-quick").await.unwrap();
+        assert_eq!(
+            quick.latest().unwrap().status,
+            history::RunStatus::Succeeded
+        );
+        let slow = history::History::load("drain-slow").await.unwrap();
+        assert_eq!(
+            slow.latest().unwrap().status,
+            history::RunStatus::Interrupted
+        );
+        // Waiting for a free slot at shutdown, so it never started
+        let pending = history::History::load("drain-pending").await.unwrap();
+        assert!(pending.runs.is_empty());
+
+        // An interrupted run is not the task's fault
+        let state = state::State::load().await.unwrap();
+        assert_eq!(state.tasks["drain-slow"].consecutive_failures, 0);
+        assert_eq!(state.tasks["drain-slow"].pid, None);
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_interrupts_abandoned_runs() {
+        let (_temp_dir, _guard) = test_home().await;
+
+        let mut storage = storage::Storage::new();
+        storage.events.push(storage::Event {
+            slug: "abandoned".to_string(),
+            cron: "0 0 0 1 1 *".to_string(),
+            command: "true".to_string(),
+            ..Default::default()
+        });
+        storage.save().await.unwrap();
+
+        // A scheduler died mid-run, leaving the record and its live log
+        let finished = history::RunRecord {
+            status: history::RunStatus::Succeeded,
+            ..history::RunRecord::started("abandoned", chrono::Utc::now())
+        };
+        history::History::record(finished, 10).await.unwrap();
+        let left = history::RunRecord::started("abandoned", chrono::Utc::now());
+        let left = history::History::record(left, 10).await.unwrap();
+        let live_log = history::History::live_log_path("abandoned", left.id).unwrap();
+        std::fs::write(&live_log, "partial\n").unwrap();
+
+        let mut scheduler = Scheduler::new();
+        scheduler.load_events().await.unwrap();
+        scheduler.interrupt_abandoned_runs().await.unwrap();
+
+        let history = history::History::load("abandoned").await.unwrap();
+        let statuses: Vec<_> = history.runs.iter().map(|run| run.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                history::RunStatus::Succeeded,
+                history::RunStatus::Interrupted
+            ]
+        );
+        assert!(!live_log.exists());
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_reload_keeps_running_tasks() {
+        let (_temp_dir, _guard) = test_home().await;
+
+        let missed_yearly = |slug: &str, command: &str| storage::Event {
+            slug: slug.to_string(),
+            // Missed its yearly slot, so it runs once when first seen
+            cron: "0 0 0 1 1 *".to_string(),
+            created_at: chrono::Utc::now() - chrono::Duration::days(400),
+            command: command.to_string(),
+            ..Default::default()
+        };
+        let mut storage = storage::Storage::new();
+        storage.events.push(missed_yearly("reload-slow", "sleep 2"));
+        storage.events.push(missed_yearly("reload-gone", "true"));
+        storage.save().await.unwrap();
+
+        let config = config::Config {
+            max_concurrent_runs: 1,
+            ..Default::default()
+        };
+        let mut scheduler = Scheduler::with_config(&config);
+        scheduler.load_events().await.unwrap();
+
+        // While reload-slow runs and reload-gone waits for the only slot,
+        // remove reload-gone and add reload-new
+        let reload = scheduler.reload_trigger();
+        let edit = async {
+            tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+            storage::Storage::update(|storage| {
+                storage.events.retain(|e| e.slug != "reload-gone");
+                storage.events.push(missed_yearly("reload-new", "true"));
+                Ok(())
+            })
+            .await
+            .unwrap();
+            reload.notify_one();
+            tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+        };
+        scheduler.run_until(edit).await.unwrap();
+        scheduler.drain(std::time::Duration::from_secs(5)).await;
+
+        let slow = history::History::load("reload-slow").await.unwrap();
+        assert_eq!(slow.runs.len(), 1);
+        assert_eq!(slow.latest().unwrap().status, history::RunStatus::Succeeded);
+        let gone = history::History::load("reload-gone").await.unwrap();
+        assert!(gone.runs.is_empty());
+        let new = history::History::load("reload-new").await.unwrap();
+        assert_eq!(new.latest().unwrap().status, history::RunStatus::Succeeded);
+
+        let state = state::State::load().await.unwrap();
+        assert!(!state.tasks.contains_key("reload-gone"));
+    }
+
+    #[tokio::test]
+    async fn test_control_socket_requests() {
+        use control::{Reply, Request};
+
+        let (_temp_dir, _guard) = test_home().await;
+
+        let mut storage = storage::Storage::new();
+        for slug in ["ctl-a", "ctl-b"] {
+            storage.events.push(storage::Event {
+                slug: slug.to_string(),
+                cron: "0 0 0 1 1 *".to_string(),
+                command: "true".to_string(),
+                ..Default::default()
+            });
+        }
+        storage.save().await.unwrap();
+
+        let mut scheduler = Scheduler::new();
+        scheduler.load_events().await.unwrap();
+
+        // Nobody is listening yet
+        assert_eq!(control::request(&Request::Status).await.unwrap(), None);
+
+        let listener = control::bind().await.unwrap();
+        assert!(control::bind().await.is_err());
+        let stop = std::sync::Arc::new(tokio::sync::Notify::new());
+        let server = tokio::spawn({
+            let stop = stop.clone();
+            control::serve(listener, scheduler.control_calls(), async move {
+                stop.notified().await
+            })
+        });
+
+        let send = |request| async move { control::request(&request).await };
+        let client = async {
+            match send(Request::Status).await.unwrap() {
+                Some(Reply::Status(status)) => {
+                    assert_eq!(status.pid, std::process::id());
+                    assert_eq!((status.tasks, status.active_tasks), (2, 2));
+                    assert!(status.next_fire.is_some());
+                }
+                other => panic!("unexpected reply {other:?}"),
+            }
+
+            let paused = send(Request::Pause {
+                slugs: vec!["ctl-b".to_string(), "nope".to_string()],
+            })
+            .await
+            .unwrap();
+            let expected = Reply::Updated {
+                found: 1,
+                changed: 1,
+                missing: vec!["nope".to_string()],
+            };
+            assert_eq!(paused, Some(expected));
+
+            match send(Request::List).await.unwrap() {
+                Some(Reply::Tasks(tasks)) => {
+                    let b = tasks.iter().find(|t| t.slug == "ctl-b").unwrap();
+                    assert!(!b.active);
+                    assert_eq!(b.next_run, None);
+                }
+                other => panic!("unexpected reply {other:?}"),
+            }
+
+            let resumed = send(Request::Resume { slugs: vec![] }).await.unwrap();
+            let expected = Reply::Updated {
+                found: 2,
+                changed: 1,
+                missing: vec![],
+            };
+            assert_eq!(resumed, Some(expected));
+
+            let unknown = send(Request::Trigger {
+                slug: "nope".to_string(),
+            })
+            .await;
+            assert!(unknown.unwrap_err().to_string().contains("not found"));
+            let triggered = send(Request::Trigger {
+                slug: "ctl-a".to_string(),
+            })
+            .await
+            .unwrap();
+            assert_eq!(triggered, Some(Reply::Done));
+            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+            assert_eq!(send(Request::Reload).await.unwrap(), Some(Reply::Done));
+            let stopping = send(Request::Shutdown).await.unwrap();
+            let expected = Reply::Stopping {
+                pid: std::process::id(),
+            };
+            assert_eq!(stopping, Some(expected));
+
+            // The scheduler returns on its own after a shutdown request
+            std::future::pending::<()>().await
+        };
+        tokio::time::timeout(
+            std::time::Duration::from_secs(10),
+            scheduler.run_until(client),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        scheduler.drain(std::time::Duration::from_secs(5)).await;
+        stop.notify_one();
+        server.await.unwrap();
+
+        let a = history::History::load("ctl-a").await.unwrap();
+        assert_eq!(a.latest().unwrap().status, history::RunStatus::Succeeded);
+        let storage = storage::Storage::load().await.unwrap();
+        assert!(storage.events.iter().all(|e| e.active));
+    }
+
+    #[test]
+    fn test_sd_notify_messages_and_watchdog() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("notify.sock");
+        let socket = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+
+        let notifier = sd_notify::Notifier::new(path.to_str().unwrap()).unwrap();
+        notifier.notify("READY=1").unwrap();
+        let mut buf = [0; 64];
+        let len = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+
+        // Half the requested interval, and only for the named process
+        let interval = sd_notify::parse_watchdog(Some("3000000"), None, 42);
+        assert_eq!(interval, Some(std::time::Duration::from_millis(1500)));
+        assert!(sd_notify::parse_watchdog(Some("3000000"), Some("42"), 42).is_some());
+        assert!(sd_notify::parse_watchdog(Some("3000000"), Some("7"), 42).is_none());
+        assert!(sd_notify::parse_watchdog(Some("0"), None, 42).is_none());
+        assert!(sd_notify::parse_watchdog(None, None, 42).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_fires_second_level_schedules_on_time() {
+        let (_temp_dir, _guard) = test_home().await;
+
+        let mut storage = storage::Storage::new();
+        storage.events.push(storage::Event {
+            slug: "every-second".to_string(),
+            cron: "* * * * * *".to_string(),
+            command: "true".to_string(),
+            // Already caught up, so only on-schedule firings count
+            last_run: Some(chrono::Utc::now()),
+            ..Default::default()
+        });
+        storage.save().await.unwrap();
+
+        let mut scheduler = Scheduler::new();
+        scheduler.load_events().await.unwrap();
+        let _ = tokio::time::timeout(std::time::Duration::from_millis(3500), scheduler.run()).await;
+
+        let loaded = history::History::load("every-second").await.unwrap();
+        assert!(
+            loaded.runs.len() >= 3,
+            "expected a run every second, got {}",
+            loaded.runs.len()
+        );
+
+        // Each run starts right at the top of its second
+        for run in &loaded.runs {
+            let offset = run.started_at.timestamp_subsec_millis();
+            assert!(offset < 300, "run started {offset}ms late");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_concurrency_policies() {
+        use control::{Reply, Request, Response, TaskStatus};
+        use history::RunStatus::*;
+        use storage::ConcurrencyPolicy;
+
+        let (_temp_dir, _guard) = test_home().await;
+
+        let mut storage = storage::Storage::new();
+        let policies = [
+            ("policy-skip", ConcurrencyPolicy::Skip, "sleep 2"),
+            ("policy-queue", ConcurrencyPolicy::Queue, "sleep 2"),
+            ("policy-allow", ConcurrencyPolicy::Allow, "sleep 2"),
+            // Takes a second to exit after SIGTERM
+            (
+                "policy-replace",
+                ConcurrencyPolicy::Replace,
+                "trap 'sleep 1; exit 0' TERM; sleep 30 & wait",
+            ),
+        ];
+        for (slug, concurrency_policy, command) in policies {
+            storage.events.push(storage::Event {
+                slug: slug.to_string(),
+                cron: "0 0 0 1 1 *".to_string(),
+                command: command.to_string(),
+                shell: Some(storage::DEFAULT_SHELL.to_string()),
+                concurrency_policy,
+                ..Default::default()
+            });
+        }
+        storage.save().await.unwrap();
+
+        let mut scheduler = Scheduler::new();
+        scheduler.load_events().await.unwrap();
+
+        let calls = scheduler.control_calls();
+        let call = |request| {
+            let calls = calls.clone();
+            async move {
+                let (respond, response) = tokio::sync::oneshot::channel();
+                calls.send(control::Call { request, respond }).unwrap();
+                response.await.unwrap()
+            }
+        };
+        let trigger = |slug: &str| {
+            call(Request::Trigger {
+                slug: slug.to_string(),
+            })
+        };
+        let task = |slug: &'static str| {
+            let list = call(Request::List);
+            async move {
+                match list.await {
+                    Response::Ok(Reply::Tasks(tasks)) => {
+                        tasks.into_iter().find(|t| t.slug == slug).unwrap()
+                    }
+                    other => panic!("unexpected response {other:?}"),
+                }
+            }
+        };
+        let pause = |millis| tokio::time::sleep(std::time::Duration::from_millis(millis));
+
+        let client = async {
+            for (slug, _, _) in policies {
+                assert!(matches!(trigger(slug).await, Response::Ok(Reply::Done)));
+            }
+            pause(300).await;
+            let first = |task: TaskStatus| task.pids[0];
+            let queue_pid = first(task("policy-queue").await);
+            let replace_pid = first(task("policy-replace").await);
+
+            // Fired again while every first run is in progress
+            assert!(matches!(trigger("policy-skip").await, Response::Error(_)));
+            for slug in [
+                "policy-queue",
+                "policy-queue",
+                "policy-allow",
+                "policy-replace",
+            ] {
+                assert!(matches!(trigger(slug).await, Response::Ok(Reply::Done)));
+            }
+            pause(300).await;
+
+            let skip = task("policy-skip").await;
+            assert_eq!((skip.pids.len(), skip.queued), (1, 0));
+            let queue = task("policy-queue").await;
+            assert_eq!((queue.pids.clone(), queue.queued), (vec![queue_pid], 1));
+            let allow = task("policy-allow").await;
+            assert_eq!(allow.pids.len(), 2);
+            assert_ne!(allow.pids[0], allow.pids[1]);
+            // The replacement waits for the cancelled run to exit
+            let replace = task("policy-replace").await;
+            assert_eq!(
+                (replace.pids.clone(), replace.queued),
+                (vec![replace_pid], 1)
+            );
+
+            pause(1400).await;
+            let replace = task("policy-replace").await;
+            assert_eq!((replace.pids.len(), replace.queued), (1, 0));
+            assert_ne!(replace.pids[0], replace_pid);
+
+            // The queued run starts once the first one has finished
+            pause(1000).await;
+            let queue = task("policy-queue").await;
+            assert_eq!((queue.pids.len(), queue.queued), (1, 0));
+            assert_ne!(queue.pids[0], queue_pid);
+        };
+        scheduler.run_until(client).await.unwrap();
+        scheduler.drain(std::time::Duration::from_secs(3)).await;
+
+        let statuses = |slug: &str| {
+            let slug = slug.to_string();
+            async move {
+                let history = history::History::load(&slug).await.unwrap();
+                let mut runs: Vec<_> = history.runs.iter().map(|r| r.status).collect();
+                runs.sort_by_key(|status| status.to_string());
+                runs
+            }
+        };
+        assert_eq!(statuses("policy-skip").await, vec![Succeeded]);
+        assert_eq!(statuses("policy-queue").await, vec![Succeeded, Succeeded]);
+        assert_eq!(statuses("policy-allow").await, vec![Succeeded, Succeeded]);
+        assert_eq!(
+            statuses("policy-replace").await,
+            vec![Cancelled, Interrupted]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_misfire_policies() {
+        let (_temp_dir, _guard) = test_home().await;
+
+        let policies = [
+            ("missed-once", storage::MisfirePolicy::RunOnce),
+            ("missed-all", storage::MisfirePolicy::RunAllMissed),
+            ("missed-skip", storage::MisfirePolicy::SkipToNext),
+        ];
+
+        let mut storage = storage::Storage::new();
+        for (slug, misfire_policy) in policies {
+            storage.events.push(storage::Event {
+                slug: slug.to_string(),
+                // Five yearly slots missed since the task was created
+                cron: "0 0 0 1 1 *".to_string(),
+                command: "true".to_string(),
+                created_at: chrono::Utc::now() - chrono::Duration::days(5 * 366),
+                misfire_policy,
+                max_missed_runs: Some(3),
+                ..Default::default()
+            });
+        }
+        // A new task waits for its first slot instead of running right away
+        storage.events.push(storage::Event {
+            slug: "brand-new".to_string(),
+            cron: "0 0 0 1 1 *".to_string(),
+            command: "true".to_string(),
+            ..Default::default()
+        });
+        storage.save().await.unwrap();
+
+        let mut scheduler = Scheduler::new();
+        scheduler.load_events().await.unwrap();
+        let _ = tokio::time::timeout(std::time::Duration::from_millis(1500), scheduler.run()).await;
+
+        let runs = |slug: &'static str| async move {
+            history::History::load(slug).await.unwrap().runs.len()
+        };
+        assert_eq!(runs("missed-once").await, 1);
+        assert_eq!(runs("missed-all").await, 3);
+        assert_eq!(runs("missed-skip").await, 0);
+        assert_eq!(runs("brand-new").await, 0);
+
+        // Catch-up runs record the slot they stood in for
+        let loaded = storage::Storage::load().await.unwrap();
+        let last_run = |slug: &str| {
+            let event = loaded.events.iter().find(|e| e.slug == slug).unwrap();
+            event.last_run
+        };
+        assert_eq!(last_run("missed-once"), last_run("missed-all"));
+        assert!(last_run("missed-skip").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_runner_cancel_terminates_run() {
+        let (_temp_dir, _guard) = test_home().await;
+        let backend = backend::Backend::open().await.unwrap();
+
+        let event = storage::Event {
+            slug: "cancel-task".to_string(),
+            command: "sleep 30".to_string(),
+            argv: Some(vec!["sleep".to_string(), "30".to_string()]),
+            ..Default::default()
+        };
+
+        let control = runner::RunControl::new();
+        let canceller = control.clone();
+        let (pid_tx, pid_rx) = tokio::sync::oneshot::channel();
+
+        let run = tokio::spawn(async move {
+            runner::run(&backend, &event, &control, |pid| {
+                let _ = pid_tx.send(pid);
+            })
+            .await
+        });
+
+        let pid = pid_rx.await.unwrap();
+        assert!(pid > 0);
+        canceller.cancel();
+
+        let record = tokio::time::timeout(std::time::Duration::from_secs(10), run)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.status, history::RunStatus::Cancelled);
+        assert!(record.signal.is_some());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(cli::parse_duration("90"), Ok(90));
+        assert_eq!(cli::parse_duration("30s"), Ok(30));
+        assert_eq!(cli::parse_duration("5m"), Ok(300));
+        assert_eq!(cli::parse_duration("2h"), Ok(7200));
+        assert_eq!(cli::parse_duration("1d"), Ok(86400));
+        assert!(cli::parse_duration("0").is_err());
+        assert!(cli::parse_duration("soon").is_err());
+        assert!(cli::parse_duration("").is_err());
+        assert!(cli::parse_duration("99999999999999999d").is_err());
+    }
+
+    #[test]
+    fn test_truncate_output_keeps_tail() {
+        let (text, truncated) = history::truncate_output(b"short");
+        assert_eq!(text, "short");
+        assert!(!truncated);
+
+        let mut big = vec![b'a'; history::MAX_OUTPUT_BYTES];
+        big.extend_from_slice(b"the end");
+        let (text, truncated) = history::truncate_output(&big);
+        assert!(truncated);
+        assert_eq!(text.len(), history::MAX_OUTPUT_BYTES);
+        assert!(text.ends_with("the end"));
+    }
+
+    #[test]
+    fn test_task_schedule_timezones_and_dst() {
+        use chrono::TimeZone;
+        let utc = |y, m, d, h, min| chrono::Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap();
+
+        // 2 AM in Berlin is 1 AM UTC in winter
+        let berlin = schedule::TaskSchedule::new("0 0 2 * * *", Some("Europe/Berlin")).unwrap();
+        assert_eq!(
+            berlin.next_after(utc(2025, 1, 15, 12, 0)),
+            Some(utc(2025, 1, 16, 1, 0))
+        );
+
+        // New York skips 02:00-03:00 on 2025-03-09: the 02:30 run happens at
+        // 03:00 EDT instead of being lost
+        let daily = schedule::TaskSchedule::new("0 30 2 * * *", Some("America/New_York")).unwrap();
+        let runs: Vec<_> = daily.after(utc(2025, 3, 8, 12, 0)).take(2).collect();
+        assert_eq!(runs, vec![utc(2025, 3, 9, 7, 0), utc(2025, 3, 10, 6, 30)]);
+
+        // 01:00-02:00 repeats on 2025-11-02: 01:30 runs on the first pass only
+        let daily = schedule::TaskSchedule::new("0 30 1 * * *", Some("America/New_York")).unwrap();
+        let runs: Vec<_> = daily.after(utc(2025, 11, 1, 12, 0)).take(2).collect();
+        assert_eq!(runs, vec![utc(2025, 11, 2, 5, 30), utc(2025, 11, 3, 6, 30)]);
+
+        let hourly = schedule::TaskSchedule::new("0 30 * * * *", Some("America/New_York")).unwrap();
+        let runs: Vec<_> = hourly.after(utc(2025, 11, 2, 5, 0)).take(2).collect();
+        assert_eq!(runs, vec![utc(2025, 11, 2, 5, 30), utc(2025, 11, 2, 7, 30)]);
+
+        // Without a timezone schedules stay in UTC
+        let plain = schedule::TaskSchedule::new("0 0 2 * * *", None).unwrap();
+        assert_eq!(
+            plain.next_after(utc(2025, 1, 15, 12, 0)),
+            Some(utc(2025, 1, 16, 2, 0))
+        );
+
+        assert!(schedule::TaskSchedule::new("0 0 2 * * *", Some("Mars/Olympus")).is_err());
+    }
+
+    #[test]
+    fn test_schedule_parsing() {
         let valid_crons = vec![
             "* * * * * *",
             "0 0 * * * *",
             "*/5 * * * * *",
             "0 9-17 * * * MON-FRI *",
+            // Standard crontab lines and macros
+            "* * * * *",
+            "*/5 * * * *",
+            "0 9 * * 1-5",
+            "0 0 */2 * 1",
+            "0 0 0 1 * MON",
+            "@hourly",
+            "@daily",
+            "@weekly",
+            "@reboot",
         ];
 
         for cron_str in valid_crons {
             assert!(
-                Schedule::from_str(cron_str).is_ok(),
+                schedule::TaskSchedule::new(cron_str, None).is_ok(),
                 "Failed to parse: {}",
                 cron_str
             );
@@ -136,15 +1833,47 @@ mod tests {
             "invalid",
             "* * * *",
             "60 * * * * *",
-            "* * * * *", // 5 fields instead of 6
+            "0 9 * * 8",
+            "0 9 * * 5-1",
+            // Crontab would run these when either day matches
+            "0 0 1 * 1",
+            "0 0 1-7 * MON",
+            "@fortnightly",
         ];
 
         for cron_str in invalid_crons {
             assert!(
-                Schedule::from_str(cron_str).is_err(),
+                schedule::TaskSchedule::new(cron_str, None).is_err(),
                 "Should have failed: {}",
                 cron_str
             );
         }
     }
+
+    #[test]
+    fn test_crontab_syntax_matches_six_field_schedules() {
+        use chrono::TimeZone;
+        // A Sunday
+        let start = chrono::Utc.with_ymd_and_hms(2025, 1, 5, 12, 0, 0).unwrap();
+        let next = |cron: &str| {
+            let schedule = schedule::TaskSchedule::new(cron, None).unwrap();
+            schedule.after(start).take(3).collect::<Vec<_>>()
+        };
+
+        assert_eq!(next("*/5 * * * *"), next("0 */5 * * * *"));
+        assert_eq!(next("@hourly"), next("0 0 * * * *"));
+        assert_eq!(next("@midnight"), next("0 0 0 * * *"));
+        assert_eq!(next("@weekly"), next("0 0 0 * * SUN"));
+
+        // Crontab weekdays count Sunday as 0 or 7
+        assert_eq!(next("30 9 * * 1-5"), next("0 30 9 * * MON-FRI"));
+        assert_eq!(next("0 8 * * 0"), next("0 0 8 * * SUN"));
+        assert_eq!(next("0 8 * * 7"), next("0 0 8 * * SUN"));
+        assert_eq!(next("0 8 * * 5-7"), next("0 0 8 * * FRI,SAT,SUN"));
+        assert_eq!(next("0 8 * * */2"), next("0 0 8 * * SUN,TUE,THU,SAT"));
+
+        let reboot = schedule::TaskSchedule::new("@reboot", None).unwrap();
+        assert!(reboot.is_reboot());
+        assert_eq!(reboot.next_after(start), None);
+    }
 }
@@ -1,33 +1,98 @@
 use anyhow::Result;
 use clap::Parser;
 
+mod backend;
 mod cli;
+mod config;
+mod control;
+mod crontab;
 mod daemon;
+mod export;
+mod history;
+mod migrations;
+mod paths;
+mod runner;
+mod schedule;
 mod scheduler;
+mod sd_notify;
+mod state;
 mod storage;
+mod taskfile;
 mod tui;
 
 use cli::Cli;
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    env_logger::init();
-
+fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    // Log to stderr; in the foreground that is the supervisor's log, so
+    // include progress by default, and elsewhere show warnings such as a
+    // corrupt task file
+    let foreground = matches!(
+        cli.command,
+        cli::Commands::Run {
+            foreground: true,
+            ..
+        }
+    );
+    let default_level = if foreground { "info" } else { "warn" };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(default_level))
+        .init();
+    paths::init(cli.data_dir.as_deref(), cli.profile.as_deref())?;
+
+    // The daemon forks before any runtime threads exist and builds its own
+    if let cli::Commands::Run {
+        daemonize: true, ..
+    } = cli.command
+    {
+        return daemon::daemonize();
+    }
+
+    // Still single-threaded, so the service manager's variables can be taken
+    // out of the environment before tasks get to inherit them
+    let notifier = if foreground {
+        let notifier = sd_notify::Notifier::from_env()?;
+        sd_notify::remove_env();
+        notifier
+    } else {
+        None
+    };
+
+    tokio::runtime::Runtime::new()?.block_on(run(cli, notifier))
+}
+
+async fn run(cli: Cli, notifier: Option<sd_notify::Notifier>) -> Result<()> {
     match cli.command {
         cli::Commands::Add {
             slug,
             cron,
+            shell,
+            history_limit,
+            timeout,
+            concurrency_policy,
+            misfire_policy,
+            max_missed_runs,
+            misfire_grace,
+            timezone,
             command,
         } => {
-            cli::handle_add(slug, cron, command).await?;
+            let options = cli::AddOptions {
+                shell,
+                history_limit,
+                timeout,
+                concurrency_policy,
+                misfire_policy,
+                max_missed_runs,
+                misfire_grace,
+                timezone,
+            };
+            cli::handle_add(slug, cron, command, options).await?;
         }
         cli::Commands::Remove { slug } => {
             cli::handle_remove(slug).await?;
         }
-        cli::Commands::List => {
-            cli::handle_list().await?;
+        cli::Commands::List { all_profiles } => {
+            cli::handle_list(all_profiles).await?;
         }
         cli::Commands::Start { slugs, all } => {
             cli::handle_start(slugs, all).await?;
@@ -35,9 +100,47 @@ async fn main() -> Result<()> {
         cli::Commands::Stop { slugs, all } => {
             cli::handle_stop(slugs, all).await?;
         }
+        cli::Commands::Status => {
+            cli::handle_status().await?;
+        }
+        cli::Commands::Trigger { slug } => {
+            cli::handle_trigger(slug).await?;
+        }
+        cli::Commands::Run {
+            foreground,
+            pid_file,
+            ..
+        } => {
+            cli::handle_run(foreground, pid_file, notifier).await?;
+        }
         cli::Commands::Tui => {
             cli::handle_tui().await?;
         }
+        cli::Commands::History { slug, limit } => {
+            cli::handle_history(slug, limit).await?;
+        }
+        cli::Commands::Logs { slug, run, follow } => {
+            cli::handle_logs(slug, run, follow).await?;
+        }
+        cli::Commands::MigrateStorage { to, from } => {
+            cli::handle_migrate_storage(from, to).await?;
+        }
+        cli::Commands::Apply { file, prune } => {
+            cli::handle_apply(file, prune).await?;
+        }
+        cli::Commands::Diff { file, prune } => {
+            cli::handle_diff(file, prune).await?;
+        }
+        cli::Commands::Export {
+            slugs,
+            format,
+            output,
+        } => {
+            cli::handle_export(slugs, format, output).await?;
+        }
+        cli::Commands::ImportCrontab { file, dry_run } => {
+            cli::handle_import_crontab(file, dry_run).await?;
+        }
     }
 
     Ok(())
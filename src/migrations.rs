@@ -0,0 +1,122 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use crate::state::State;
+
+/// Layout version of `events.json` written by this build.
+///
+/// Bump it together with a new step in [`MIGRATIONS`] whenever the stored
+/// format changes in a way older builds can't read.
+pub const SCHEMA_VERSION: u64 = 2;
+
+/// Upgrades a document by one version, possibly moving data into the runtime
+/// state.
+type Migration = fn(&mut Value, &mut State) -> Result<()>;
+
+/// `MIGRATIONS[n]` upgrades a version `n` document to version `n + 1`.
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [v0_to_v1, v1_to_v2];
+
+/// Layout version of a stored document.
+///
+/// Files from before versioning have no `version` field and count as version 0.
+/// A document from a newer build is refused, since loading and saving it here
+/// would silently drop whatever this build doesn't know about.
+pub fn schema_version(doc: &Value) -> Result<u64> {
+    let version = match doc.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| anyhow::anyhow!("Invalid schema version {}", version))?,
+    };
+
+    ensure_supported("events.json", version)?;
+    Ok(version)
+}
+
+/// Refuse stored data whose schema `version` is newer than this build's.
+pub fn ensure_supported(source: &str, version: u64) -> Result<()> {
+    if version > SCHEMA_VERSION {
+        return Err(anyhow::anyhow!(
+            "{} has schema version {}, but this singleschedule only supports up to version {}; upgrade singleschedule to use it",
+            source,
+            version,
+            SCHEMA_VERSION
+        ));
+    }
+
+    Ok(())
+}
+
+/// Upgrade `doc` to [`SCHEMA_VERSION`] one step at a time.
+pub fn migrate(doc: &mut Value, state: &mut State) -> Result<()> {
+    let version = schema_version(doc)?;
+
+    for (from, step) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        step(doc, state).with_context(|| {
+            format!(
+                "Failed to migrate events.json from schema version {} to {}",
+                from,
+                from + 1
+            )
+        })?;
+        set_version(doc, from as u64 + 1)?;
+    }
+
+    Ok(())
+}
+
+/// Version 0 relied on a serde default for tasks saved before `active`
+/// existed; spell it out.
+pub(crate) fn v0_to_v1(doc: &mut Value, _state: &mut State) -> Result<()> {
+    for event in events_mut(doc)? {
+        event
+            .as_object_mut()
+            .ok_or_else(|| anyhow::anyhow!("Task is not an object"))?
+            .entry("active")
+            .or_insert(Value::Bool(true));
+    }
+
+    Ok(())
+}
+
+/// Version 1 kept `pid` and `last_run` inline; move them to `state.json`.
+pub(crate) fn v1_to_v2(doc: &mut Value, state: &mut State) -> Result<()> {
+    for event in events_mut(doc)? {
+        let event = event
+            .as_object_mut()
+            .ok_or_else(|| anyhow::anyhow!("Task is not an object"))?;
+
+        // A PID from before the upgrade belongs to a daemon that is no longer
+        // tracking it, so it is dropped rather than carried over
+        event.remove("pid");
+        let last_run: Option<DateTime<Utc>> = match event.remove("last_run") {
+            Some(value) => serde_json::from_value(value)?,
+            None => None,
+        };
+
+        if let Some(last_run) = last_run {
+            let slug = event
+                .get("slug")
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow::anyhow!("Task has no slug"))?;
+            let task = state.tasks.entry(slug.to_string()).or_default();
+            task.last_run = task.last_run.max(Some(last_run));
+        }
+    }
+
+    Ok(())
+}
+
+fn events_mut(doc: &mut Value) -> Result<&mut Vec<Value>> {
+    doc.get_mut("events")
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| anyhow::anyhow!("Missing `events` list"))
+}
+
+fn set_version(doc: &mut Value, version: u64) -> Result<()> {
+    doc.as_object_mut()
+        .ok_or_else(|| anyhow::anyhow!("Document is not an object"))?
+        .insert("version".to_string(), Value::from(version));
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Environment variable naming a single directory for all of singleschedule's
+/// files; `--data-dir` sets it for the current process.
+pub const HOME_ENV: &str = "SINGLESCHEDULE_HOME";
+
+/// Environment variable selecting the profile; `--profile` sets it for the
+/// current process.
+pub const PROFILE_ENV: &str = "SINGLESCHEDULE_PROFILE";
+
+/// Profile used when none is selected; its files live directly in the data
+/// directories.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Where files live: either one directory for everything (an override or the
+/// legacy `~/.singleschedule`), or split the XDG way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dirs {
+    /// Task definitions and `config.json`.
+    pub config: PathBuf,
+    /// Runtime state, run history and logs, and the daemon's PID file.
+    pub state: PathBuf,
+}
+
+impl Dirs {
+    /// Directories of the selected profile (see [`Dirs::base`] and
+    /// [`Dirs::profile`]).
+    pub fn resolve() -> Result<Self> {
+        Ok(Self::base()?.profile(&current_profile()?))
+    }
+
+    /// Resolve the data directories, in order of precedence:
+    ///
+    /// 1. `$SINGLESCHEDULE_HOME` (or `--data-dir`), holding everything
+    /// 2. `~/.singleschedule`, if it exists and nothing has been set up the
+    ///    XDG way yet
+    /// 3. `$XDG_CONFIG_HOME/singleschedule` and `$XDG_STATE_HOME/singleschedule`
+    pub fn base() -> Result<Self> {
+        if let Some(dir) = std::env::var_os(HOME_ENV).filter(|dir| !dir.is_empty()) {
+            return Ok(Self::single(PathBuf::from(dir)));
+        }
+
+        let base = directories::BaseDirs::new()
+            .ok_or_else(|| anyhow::anyhow!("Failed to get home directory"))?;
+
+        let config = base.config_dir().join("singleschedule");
+        let legacy = base.home_dir().join(".singleschedule");
+        if legacy.is_dir() && !config.exists() {
+            return Ok(Self::single(legacy));
+        }
+
+        // Only Linux has a state directory; elsewhere keep state with other
+        // local application data
+        let state = base
+            .state_dir()
+            .unwrap_or_else(|| base.data_local_dir())
+            .join("singleschedule");
+
+        Ok(Dirs { config, state })
+    }
+
+    /// Directories of a profile within these data directories: the default
+    /// profile uses them as they are, any other one its own `profiles/<name>`
+    /// subdirectory of each.
+    pub fn profile(self, name: &str) -> Self {
+        if name == DEFAULT_PROFILE {
+            return self;
+        }
+
+        Dirs {
+            config: self.config.join("profiles").join(name),
+            state: self.state.join("profiles").join(name),
+        }
+    }
+
+    /// Names of all profiles that have files, the default one first.
+    pub fn profiles(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for dir in [&self.config, &self.state] {
+            let Ok(entries) = std::fs::read_dir(dir.join("profiles")) else {
+                continue;
+            };
+            for entry in entries {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() {
+                    if let Some(name) = entry.file_name().to_str() {
+                        names.push(name.to_string());
+                    }
+                }
+            }
+        }
+
+        names.retain(|name| validate_profile(name).is_ok() && name != DEFAULT_PROFILE);
+        names.sort();
+        names.dedup();
+        names.insert(0, DEFAULT_PROFILE.to_string());
+        Ok(names)
+    }
+
+    fn single(dir: PathBuf) -> Self {
+        Dirs {
+            config: dir.clone(),
+            state: dir,
+        }
+    }
+}
+
+/// Directory holding task definitions and `config.json`.
+pub fn config_dir() -> Result<PathBuf> {
+    Ok(Dirs::resolve()?.config)
+}
+
+/// Directory holding runtime state, run history and the daemon's PID file.
+pub fn state_dir() -> Result<PathBuf> {
+    Ok(Dirs::resolve()?.state)
+}
+
+/// The selected profile: `$SINGLESCHEDULE_PROFILE` (or `--profile`), else
+/// the default one.
+pub fn current_profile() -> Result<String> {
+    match std::env::var(PROFILE_ENV) {
+        Ok(name) if !name.is_empty() => {
+            validate_profile(&name)?;
+            Ok(name)
+        }
+        _ => Ok(DEFAULT_PROFILE.to_string()),
+    }
+}
+
+/// Profile names become directory names, so keep them to a safe alphabet.
+pub fn validate_profile(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(anyhow::anyhow!(
+            "Invalid profile name '{}' (use letters, digits, '-', '_' and '.')",
+            name
+        ));
+    }
+
+    Ok(())
+}
+
+/// Pin the `--data-dir` override (or a relative `$SINGLESCHEDULE_HOME`) as an
+/// absolute path in the environment, along with the `--profile` selection, so
+/// the daemon, which changes its working directory, and any singleschedule
+/// invoked by a task use the same files.
+pub fn init(data_dir: Option<&Path>, profile: Option<&str>) -> Result<()> {
+    if let Some(profile) = profile {
+        validate_profile(profile)?;
+        std::env::set_var(PROFILE_ENV, profile);
+    }
+    current_profile()?;
+
+    let dir = match data_dir {
+        Some(dir) => dir.to_path_buf(),
+        None => match std::env::var_os(HOME_ENV).filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => return Ok(()),
+        },
+    };
+
+    let dir = std::path::absolute(&dir)
+        .map_err(|e| anyhow::anyhow!("Invalid data directory {}: {}", dir.display(), e))?;
+    std::env::set_var(HOME_ENV, dir);
+    Ok(())
+}
@@ -0,0 +1,329 @@
+use anyhow::Result;
+use chrono::Utc;
+use log::{debug, error, info, warn};
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::fs::{self, File};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, Command};
+use tokio::sync::{Mutex, Notify};
+use tokio::time::{self, Duration, Instant};
+
+use crate::backend::StorageBackend;
+use crate::history::{truncate_output, History, RunRecord, RunStatus, MAX_OUTPUT_BYTES};
+use crate::storage::Event;
+
+/// How long a stopped run gets to exit after SIGTERM before it is killed.
+pub const TERMINATE_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+type LiveLog = Arc<Mutex<File>>;
+
+/// Handle for stopping a run from outside while it is in progress.
+#[derive(Debug, Clone, Default)]
+pub struct RunControl {
+    cancel: Arc<Notify>,
+    /// Whether the stop comes from the daemon shutting down.
+    interrupted: Arc<AtomicBool>,
+}
+
+impl RunControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Terminate the run's process group; the run is recorded as cancelled.
+    pub fn cancel(&self) {
+        // notify_one keeps a permit, so a cancel before the spawn is not lost
+        self.cancel.notify_one();
+    }
+
+    /// Like [`RunControl::cancel`], but the run is recorded as interrupted by
+    /// the daemon shutting down.
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::SeqCst);
+        self.cancel.notify_one();
+    }
+
+    /// How a run stopped through this handle is recorded.
+    fn stopped_status(&self) -> RunStatus {
+        if self.interrupted.load(Ordering::SeqCst) {
+            RunStatus::Interrupted
+        } else {
+            RunStatus::Cancelled
+        }
+    }
+}
+
+/// Execute a task once and record the run in its history in `backend`.
+///
+/// The run is recorded as `running` before the command starts, and its output
+/// is streamed to a live log file so it can be followed while in progress.
+/// `control` can stop the run early; `on_spawn` receives the child's PID as
+/// soon as the command has started.
+pub async fn run(
+    backend: &impl StorageBackend,
+    event: &Event,
+    control: &RunControl,
+    on_spawn: impl FnOnce(u32),
+) -> RunRecord {
+    let started_at = Utc::now();
+    let timer = Instant::now();
+
+    let mut record = match backend
+        .record_run(
+            RunRecord::started(&event.slug, started_at),
+            event.history_limit(),
+        )
+        .await
+    {
+        Ok(record) => record,
+        Err(e) => {
+            error!("Failed to record start of task '{}': {}", event.slug, e);
+            RunRecord::started(&event.slug, started_at)
+        }
+    };
+
+    let live_log_path = History::live_log_path(&event.slug, record.id).ok();
+    let live_log = match &live_log_path {
+        Some(path) => open_live_log(path).await,
+        None => None,
+    };
+
+    match spawn_and_wait(event, live_log, control, on_spawn).await {
+        Ok(output) => {
+            if !output.stdout.is_empty() {
+                debug!("Command stdout: {}", output.stdout);
+            }
+            if !output.stderr.is_empty() {
+                debug!("Command stderr: {}", output.stderr);
+            }
+
+            record.exit_code = output.status.code();
+            record.signal = output.status.signal();
+            record.status = if let Some(status) = output.interrupted {
+                status
+            } else if output.status.success() {
+                RunStatus::Succeeded
+            } else if record.signal.is_some() {
+                RunStatus::Killed
+            } else {
+                RunStatus::Failed
+            };
+            record.stdout = output.stdout;
+            record.stderr = output.stderr;
+            record.output_truncated = output.truncated;
+        }
+        Err(e) => {
+            record.status = RunStatus::Failed;
+            record.error = Some(e.to_string());
+        }
+    }
+
+    record.finished_at = Some(Utc::now());
+    record.duration_ms = Some(timer.elapsed().as_millis() as u64);
+
+    if let Err(e) = backend.update_run(&record).await {
+        error!("Failed to record run of task '{}': {}", event.slug, e);
+    }
+
+    if let Some(path) = live_log_path {
+        let _ = fs::remove_file(path).await;
+    }
+
+    record
+}
+
+struct CapturedOutput {
+    status: ExitStatus,
+    stdout: String,
+    stderr: String,
+    truncated: bool,
+    /// Set when the run was stopped by us rather than exiting on its own.
+    interrupted: Option<RunStatus>,
+}
+
+async fn spawn_and_wait(
+    event: &Event,
+    live_log: Option<LiveLog>,
+    control: &RunControl,
+    on_spawn: impl FnOnce(u32),
+) -> Result<CapturedOutput> {
+    debug!("Executing command: {}", event.command);
+
+    let (program, args) = event.program_and_args()?;
+
+    // Each run gets its own process group so a timeout can take down
+    // everything the command started, not just the direct child.
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .spawn()?;
+
+    let pgid = child.id().map(|pid| Pid::from_raw(pid as i32));
+    if let Some(pid) = child.id() {
+        on_spawn(pid);
+    }
+
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let stdout_task = tokio::spawn(capture(stdout, live_log.clone()));
+    let stderr_task = tokio::spawn(capture(stderr, live_log));
+
+    let deadline = event
+        .timeout
+        .map(|secs| Instant::now() + Duration::from_secs(secs));
+
+    let mut interrupted = None;
+    let status = tokio::select! {
+        status = child.wait() => status?,
+        _ = wait_for_deadline(deadline) => {
+            warn!(
+                "Task '{}' timed out after {}s, terminating",
+                event.slug,
+                event.timeout.unwrap_or_default()
+            );
+            interrupted = Some(RunStatus::TimedOut);
+            terminate(&mut child).await?
+        }
+        _ = control.cancel.notified() => {
+            let status = control.stopped_status();
+            if status == RunStatus::Interrupted {
+                info!("Interrupting running task '{}'", event.slug);
+            } else {
+                info!("Cancelling running task '{}'", event.slug);
+            }
+            interrupted = Some(status);
+            terminate(&mut child).await?
+        }
+    };
+
+    // A background process the command left behind can hold the pipes open
+    // after the child has exited, so draining them is bounded the same way.
+    let drained =
+        async { Ok::<_, tokio::task::JoinError>((stdout_task.await?, stderr_task.await?)) };
+    tokio::pin!(drained);
+    let ((stdout, stdout_dropped), (stderr, stderr_dropped)) = tokio::select! {
+        drained = &mut drained => drained?,
+        _ = wait_for_deadline(deadline), if interrupted.is_none() => {
+            warn!(
+                "Task '{}' left processes holding its output after {}s, killing them",
+                event.slug,
+                event.timeout.unwrap_or_default()
+            );
+            interrupted = Some(RunStatus::TimedOut);
+            kill_group(pgid);
+            drained.await?
+        }
+        _ = control.cancel.notified(), if interrupted.is_none() => {
+            info!("Killing processes left behind by task '{}'", event.slug);
+            interrupted = Some(control.stopped_status());
+            kill_group(pgid);
+            drained.await?
+        }
+    };
+    let (stdout, stdout_truncated) = truncate_output(&stdout);
+    let (stderr, stderr_truncated) = truncate_output(&stderr);
+
+    Ok(CapturedOutput {
+        status,
+        stdout,
+        stderr,
+        truncated: stdout_dropped || stderr_dropped || stdout_truncated || stderr_truncated,
+        interrupted,
+    })
+}
+
+async fn wait_for_deadline(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// SIGKILL whatever is left of a run's process group.
+fn kill_group(pgid: Option<Pid>) {
+    if let Some(pgid) = pgid {
+        let _ = signal::killpg(pgid, Signal::SIGKILL);
+    }
+}
+
+/// Stop a child's process group: SIGTERM first, SIGKILL if it is still
+/// around after `TERMINATE_GRACE_PERIOD`.
+async fn terminate(child: &mut Child) -> Result<ExitStatus> {
+    let Some(pid) = child.id() else {
+        // Already reaped
+        return Ok(child.wait().await?);
+    };
+    let pgid = Pid::from_raw(pid as i32);
+
+    let _ = signal::killpg(pgid, Signal::SIGTERM);
+    if let Ok(status) = time::timeout(TERMINATE_GRACE_PERIOD, child.wait()).await {
+        // Clean up anything in the group that ignored SIGTERM
+        let _ = signal::killpg(pgid, Signal::SIGKILL);
+        return Ok(status?);
+    }
+
+    warn!("Process group {pid} ignored SIGTERM, sending SIGKILL");
+    let _ = signal::killpg(pgid, Signal::SIGKILL);
+    Ok(child.wait().await?)
+}
+
+/// Read a child's pipe to the end, mirroring it into the live log.
+///
+/// Only the tail of the output is kept in memory; the returned flag tells
+/// whether anything was dropped along the way.
+async fn capture<R: AsyncRead + Unpin>(
+    reader: Option<R>,
+    live_log: Option<LiveLog>,
+) -> (Vec<u8>, bool) {
+    let mut captured = Vec::new();
+    let mut dropped = false;
+
+    let Some(mut reader) = reader else {
+        return (captured, dropped);
+    };
+
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = match reader.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+
+        if let Some(log) = &live_log {
+            let mut file = log.lock().await;
+            let _ = file.write_all(&buf[..n]).await;
+            let _ = file.flush().await;
+        }
+
+        captured.extend_from_slice(&buf[..n]);
+        if captured.len() > 2 * MAX_OUTPUT_BYTES {
+            captured.drain(..captured.len() - MAX_OUTPUT_BYTES);
+            dropped = true;
+        }
+    }
+
+    (captured, dropped)
+}
+
+async fn open_live_log(path: &Path) -> Option<LiveLog> {
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent).await;
+    }
+
+    match File::create(path).await {
+        Ok(file) => Some(Arc::new(Mutex::new(file))),
+        Err(e) => {
+            error!("Failed to create live log {}: {}", path.display(), e);
+            None
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use anyhow::Result;
+use chrono::offset::LocalResult;
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use cron::Schedule;
+use std::str::FromStr;
+
+use crate::storage::Event;
+
+/// A task's cron schedule, evaluated on the wall clock of its timezone.
+///
+/// The cron expression is matched against local time, so DST transitions
+/// shift runs with the clock: a fire time inside a skipped hour runs as soon as
+/// the clocks have jumped forward, and a fire time inside a repeated hour runs
+/// only on its first pass.
+#[derive(Debug, Clone)]
+pub struct TaskSchedule {
+    /// `None` for `@reboot`, which runs each time the daemon starts and never
+    /// on a timer.
+    schedule: Option<Schedule>,
+    timezone: Tz,
+}
+
+impl TaskSchedule {
+    /// Parse a 6/7-field cron expression, a 5-field crontab line or an
+    /// `@`-macro such as `@daily`.
+    pub fn new(cron: &str, timezone: Option<&str>) -> Result<Self> {
+        let schedule = parse_schedule(cron)?;
+        let timezone = match timezone {
+            Some(name) => parse_timezone(name)?,
+            None => Tz::UTC,
+        };
+
+        Ok(TaskSchedule { schedule, timezone })
+    }
+
+    pub fn for_event(event: &Event) -> Result<Self> {
+        Self::new(&event.cron, event.timezone.as_deref())
+    }
+
+    /// Whether this is an `@reboot` task, which only runs at daemon startup.
+    pub fn is_reboot(&self) -> bool {
+        self.schedule.is_none()
+    }
+
+    /// The parsed cron schedule, in 6/7-field form (`None` for `@reboot`).
+    pub fn cron(&self) -> Option<&Schedule> {
+        self.schedule.as_ref()
+    }
+
+    pub fn timezone(&self) -> Tz {
+        self.timezone
+    }
+
+    /// First fire time strictly after `after`.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let schedule = self.schedule.as_ref()?;
+        let local_after = after.with_timezone(&self.timezone).naive_local();
+
+        // Walk the schedule on the local wall clock, then place each match on
+        // the real timeline
+        for naive in schedule
+            .after(&local_after.and_utc())
+            .map(|at| at.naive_utc())
+        {
+            let at = match self.timezone.from_local_datetime(&naive) {
+                LocalResult::Single(at) => at,
+                // Repeated hour: the second pass of this wall-clock time is
+                // not a new slot
+                LocalResult::Ambiguous(earliest, _) => earliest,
+                // Skipped hour: run once the clocks have jumped
+                LocalResult::None => end_of_gap(self.timezone, naive)?,
+            };
+
+            let at = at.with_timezone(&Utc);
+            if at > after {
+                return Some(at);
+            }
+        }
+
+        None
+    }
+
+    /// Fire times strictly after `after`, in order.
+    pub fn after(&self, after: DateTime<Utc>) -> impl Iterator<Item = DateTime<Utc>> + '_ {
+        std::iter::successors(self.next_after(after), move |at| self.next_after(*at))
+    }
+}
+
+/// Turn any accepted schedule spelling into a cron schedule (`None` for
+/// `@reboot`).
+///
+/// 5-field crontab lines get a leading `0` seconds field, and their day of
+/// week (0-7, Sunday being 0 or 7) is renumbered to the 1-7 (Sunday = 1) used
+/// by 6/7-field expressions. A crontab line restricting both the day of month
+/// and the day of week is refused: crontab runs it when either matches, while
+/// a cron schedule needs both to.
+fn parse_schedule(expr: &str) -> Result<Option<Schedule>> {
+    let expr = expr.trim();
+
+    let normalized = match expr.to_ascii_lowercase().as_str() {
+        "@reboot" => return Ok(None),
+        "@yearly" | "@annually" => "0 0 0 1 1 *".to_string(),
+        "@monthly" => "0 0 0 1 * *".to_string(),
+        "@weekly" => "0 0 0 * * SUN".to_string(),
+        "@daily" | "@midnight" => "0 0 0 * * *".to_string(),
+        "@hourly" => "0 0 * * * *".to_string(),
+        _ if expr.starts_with('@') => {
+            return Err(anyhow::anyhow!(
+                "Invalid cron expression: unknown macro '{}'",
+                expr
+            ));
+        }
+        _ => {
+            let fields: Vec<&str> = expr.split_whitespace().collect();
+            match fields.as_slice() {
+                // Like crontab, count a field starting with `*` as unrestricted
+                [_, _, day_of_month, _, day_of_week]
+                    if !day_of_month.starts_with('*') && !day_of_week.starts_with('*') =>
+                {
+                    return Err(anyhow::anyhow!(
+                        "Invalid cron expression: '{}' restricts both day of month and day \
+                         of week, which crontab runs when either matches; use two tasks, \
+                         or the 6-field format to require both",
+                        expr
+                    ));
+                }
+                [minute, hour, day_of_month, month, day_of_week] => format!(
+                    "0 {} {} {} {} {}",
+                    minute,
+                    hour,
+                    day_of_month,
+                    month,
+                    crontab_days_of_week(day_of_week)?
+                ),
+                _ => expr.to_string(),
+            }
+        }
+    };
+
+    Schedule::from_str(&normalized)
+        .map(Some)
+        .map_err(|e| anyhow::anyhow!("Invalid cron expression: {}", e))
+}
+
+/// Renumber a crontab day-of-week field into an explicit 1-7 list.
+fn crontab_days_of_week(field: &str) -> Result<String> {
+    if field == "*" || field == "?" {
+        return Ok(field.to_string());
+    }
+
+    let invalid = || anyhow::anyhow!("Invalid cron expression: bad day of week '{}'", field);
+    let day = |name: &str| -> Result<u32> {
+        let day = match name.to_ascii_lowercase().as_str() {
+            "sun" => 0,
+            "mon" => 1,
+            "tue" => 2,
+            "wed" => 3,
+            "thu" => 4,
+            "fri" => 5,
+            "sat" => 6,
+            number => number.parse().map_err(|_| invalid())?,
+        };
+        if day > 7 {
+            return Err(invalid());
+        }
+        Ok(day)
+    };
+
+    let mut days = [false; 7];
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<usize>().map_err(|_| invalid())?),
+            None => (item, 1),
+        };
+        if step == 0 {
+            return Err(invalid());
+        }
+
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (0, 6),
+            Some((first, last)) => (day(first)?, day(last)?),
+            // `3/2` means every other day from Wednesday on
+            None if item.contains('/') => (day(range)?, 6),
+            None => (day(range)?, day(range)?),
+        };
+        if first > last {
+            return Err(invalid());
+        }
+
+        for d in (first..=last).step_by(step) {
+            days[d as usize % 7] = true;
+        }
+    }
+
+    Ok((0..7)
+        .filter(|d| days[*d])
+        .map(|d| (d + 1).to_string())
+        .collect::<Vec<_>>()
+        .join(","))
+}
+
+/// Parse an IANA timezone name such as `Europe/Berlin`.
+pub fn parse_timezone(name: &str) -> Result<Tz> {
+    name.parse::<Tz>().map_err(|_| {
+        anyhow::anyhow!(
+            "Unknown timezone '{}' (expected an IANA name such as Europe/Berlin)",
+            name
+        )
+    })
+}
+
+/// When an active task runs next, counting from now.
+pub fn next_run(event: &Event, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    if !event.active {
+        return None;
+    }
+
+    TaskSchedule::for_event(event).ok()?.next_after(now)
+}
+
+/// Format a timestamp in the viewer's local time.
+pub fn format_local(at: DateTime<Utc>) -> String {
+    at.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+/// First instant after a local time that falls into a DST gap.
+fn end_of_gap(timezone: Tz, naive: NaiveDateTime) -> Option<DateTime<Tz>> {
+    let mut local = naive.with_second(0)?;
+
+    // Gaps are whole minutes and never longer than a day
+    for _ in 0..(2 * 24 * 60) {
+        local += chrono::Duration::minutes(1);
+        if let Some(at) = timezone.from_local_datetime(&local).earliest() {
+            return Some(at);
+        }
+    }
+
+    None
+}
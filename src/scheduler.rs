@@ -1,20 +1,79 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use cron::Schedule;
-use log::{debug, error, info};
-use std::collections::HashMap;
-use std::process::Stdio;
-use std::str::FromStr;
+use log::{debug, error, info, warn};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
 use std::sync::Arc;
-use tokio::process::Command;
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc, Mutex, Notify, OnceCell, Semaphore};
 use tokio::time::{self, Duration};
 
-use crate::storage::Storage;
+use crate::backend::{Backend, StorageBackend};
+use crate::config::Config;
+use crate::control::{Call, DaemonStatus, Reply, Request, Response, TaskStatus};
+use crate::history::{History, RunRecord, RunStatus};
+use crate::runner::{self, RunControl};
+use crate::schedule::TaskSchedule;
+use crate::sd_notify::Notifier;
+use crate::state::{State, TaskState};
+use crate::storage::{ConcurrencyPolicy, Event, MisfirePolicy, Storage};
+
+/// How often the task list is re-read, in case a change was not signalled.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
 
 pub struct Scheduler {
+    /// Opened on first use and shared with every run, so run events don't
+    /// re-read `config.json` and re-check the schema each time.
+    backend: Arc<OnceCell<Backend>>,
     storage: Arc<Mutex<Storage>>,
-    schedules: HashMap<String, Schedule>,
+    schedules: HashMap<String, TaskSchedule>,
+    /// Caps how many runs execute at once across all tasks.
+    run_slots: Arc<Semaphore>,
+    /// Runs spawned but not yet reported back, per slug.
+    running: HashMap<String, Vec<ActiveRun>>,
+    /// Runs waiting for the previous one to finish, oldest first (`queue`
+    /// policy and `run_all_missed` catch-up).
+    queued: HashMap<String, VecDeque<DateTime<Utc>>>,
+    /// Last time each task fired, whether it was started, queued or skipped.
+    last_fired: HashMap<String, DateTime<Utc>>,
+    next_token: u64,
+    run_events_tx: mpsc::UnboundedSender<RunEvent>,
+    run_events_rx: mpsc::UnboundedReceiver<RunEvent>,
+    /// Notified to re-read the task list right away.
+    reload_requests: Arc<Notify>,
+    /// Requests from the control socket.
+    calls_tx: mpsc::UnboundedSender<Call>,
+    calls_rx: mpsc::UnboundedReceiver<Call>,
+    started_at: DateTime<Utc>,
+    /// Where to send `WATCHDOG=1` from the loop, and how often.
+    watchdog: Option<(Arc<Notifier>, Duration)>,
+}
+
+struct ActiveRun {
+    token: u64,
+    pid: Option<u32>,
+    control: RunControl,
+    /// Notified to drop the run if it is still waiting for a free slot.
+    unwanted: Arc<Notify>,
+}
+
+/// Progress of a spawned run, sent back to the scheduler loop.
+enum RunEvent {
+    Started {
+        slug: String,
+        token: u64,
+        pid: u32,
+    },
+    Finished {
+        slug: String,
+        token: u64,
+        scheduled_at: DateTime<Utc>,
+        record: RunRecord,
+    },
+    /// Never started because the scheduler is draining.
+    Abandoned {
+        slug: String,
+        token: u64,
+    },
 }
 
 impl Default for Scheduler {
@@ -25,28 +84,65 @@ impl Default for Scheduler {
 
 impl Scheduler {
     pub fn new() -> Self {
+        Self::with_config(&Config::default())
+    }
+
+    pub fn with_config(config: &Config) -> Self {
+        let (run_events_tx, run_events_rx) = mpsc::unbounded_channel();
+        let (calls_tx, calls_rx) = mpsc::unbounded_channel();
+
         Scheduler {
+            backend: Arc::new(OnceCell::new()),
             storage: Arc::new(Mutex::new(Storage::new())),
             schedules: HashMap::new(),
+            run_slots: Arc::new(Semaphore::new(config.max_concurrent_runs.max(1))),
+            running: HashMap::new(),
+            queued: HashMap::new(),
+            last_fired: HashMap::new(),
+            next_token: 0,
+            run_events_tx,
+            run_events_rx,
+            reload_requests: Arc::new(Notify::new()),
+            calls_tx,
+            calls_rx,
+            started_at: Utc::now(),
+            watchdog: None,
         }
     }
 
+    /// Ping the service manager's watchdog every `interval` while
+    /// [`Scheduler::run_until`] runs. The pings come from the loop itself, so
+    /// they stop when it hangs and the manager can restart the scheduler.
+    pub fn set_watchdog(&mut self, notifier: Arc<Notifier>, interval: Duration) {
+        self.watchdog = Some((notifier, interval));
+    }
+
+    /// Where to send control requests for a running scheduler to answer; see
+    /// [`crate::control::serve`].
+    pub fn control_calls(&self) -> mpsc::UnboundedSender<Call> {
+        self.calls_tx.clone()
+    }
+
+    /// Handle for asking a running scheduler to reload its tasks, e.g. on
+    /// SIGHUP; requests made while a reload is underway are coalesced.
+    pub fn reload_trigger(&self) -> Arc<Notify> {
+        self.reload_requests.clone()
+    }
+
     pub async fn load_events(&mut self) -> Result<()> {
-        let storage = Storage::load().await?;
+        let backend = self.backend().await?;
+        let storage = Storage::load_from(&backend).await?;
 
         // Parse cron expressions for active tasks only
         for event in &storage.events {
             if event.active {
-                match Schedule::from_str(&event.cron) {
+                match TaskSchedule::for_event(event) {
                     Ok(schedule) => {
                         self.schedules.insert(event.slug.clone(), schedule);
                         info!("Loaded schedule for active task '{}'", event.slug);
                     }
                     Err(e) => {
-                        error!(
-                            "Failed to parse cron expression for task '{}': {}",
-                            event.slug, e
-                        );
+                        error!("Failed to load schedule for task '{}': {}", event.slug, e);
                     }
                 }
             } else {
@@ -54,156 +150,802 @@ impl Scheduler {
             }
         }
 
+        prune_state(&backend, &storage).await;
         *self.storage.lock().await = storage;
         Ok(())
     }
 
+    /// Record runs an earlier scheduler left marked as running, having died
+    /// before it could finish them, as interrupted, and delete their live
+    /// logs. Only call this once no other scheduler for the profile can be
+    /// running, and before anything fires.
+    pub async fn interrupt_abandoned_runs(&self) -> Result<()> {
+        let backend = self.backend().await?;
+        let slugs: Vec<String> = {
+            let storage = self.storage.lock().await;
+            storage.events.iter().map(|e| e.slug.clone()).collect()
+        };
+
+        for slug in slugs {
+            let history = match backend.load_history(&slug).await {
+                Ok(history) => history,
+                Err(e) => {
+                    error!("Failed to load history for task '{slug}': {e}");
+                    continue;
+                }
+            };
+            for run in history.runs {
+                if run.status != RunStatus::Running {
+                    continue;
+                }
+                warn!(
+                    "Run {} of task '{}' never finished; recording it as interrupted",
+                    run.id, slug
+                );
+                let run = RunRecord {
+                    status: RunStatus::Interrupted,
+                    ..run
+                };
+                if let Err(e) = backend.update_run(&run).await {
+                    error!("Failed to record run of task '{slug}': {e}");
+                }
+                if let Ok(path) = History::live_log_path(&slug, run.id) {
+                    let _ = tokio::fs::remove_file(path).await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn backend(&self) -> Result<Backend> {
+        Ok(self.backend.get_or_try_init(Backend::open).await?.clone())
+    }
+
+    /// Run until dropped.
+    // The daemon uses `run_until`; this is for library users and tests
+    #[allow(dead_code)]
     pub async fn run(&mut self) -> Result<()> {
+        self.run_until(std::future::pending()).await
+    }
+
+    /// Run until `shutdown` completes. Runs in progress are left running;
+    /// see [`Scheduler::drain`].
+    pub async fn run_until(&mut self, shutdown: impl Future<Output = ()>) -> Result<()> {
         info!("Scheduler running");
+        tokio::pin!(shutdown);
 
-        // Check every 10 seconds since cron expressions support seconds
-        let mut interval = time::interval(Duration::from_secs(10));
+        // Pick up edits to events.json; each reload recomputes the next wake-up
+        let mut reload = time::interval(RELOAD_INTERVAL);
+        reload.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        // The first tick completes immediately and the events are already loaded
+        reload.tick().await;
 
+        self.run_reboot_tasks(Utc::now()).await;
+
+        let mut watchdog = self.watchdog.clone().map(|(notifier, interval)| {
+            let mut pings = time::interval(interval);
+            pings.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+            (notifier, pings)
+        });
+
+        let reload_requests = self.reload_requests.clone();
         loop {
-            interval.tick().await;
+            self.check_and_run_tasks(Utc::now()).await;
 
-            // Reload events in case they changed
-            if let Err(e) = self.reload_events().await {
-                error!("Failed to reload events: {e}");
+            let next_fire = self.next_fire_time().await;
+            match next_fire {
+                Some(next) => debug!("Next task fires at {next}"),
+                None => debug!("No upcoming runs scheduled"),
+            }
+
+            tokio::select! {
+                _ = sleep_until(next_fire) => {}
+                notifier = watchdog_due(&mut watchdog) => {
+                    if let Err(e) = notifier.notify("WATCHDOG=1") {
+                        warn!("{e:#}");
+                    }
+                }
+                _ = reload.tick() => {
+                    if let Err(e) = self.reload().await {
+                        error!("Failed to reload events: {e}");
+                    }
+                }
+                _ = reload_requests.notified() => {
+                    info!("Reloading tasks");
+                    if let Err(e) = self.reload().await {
+                        error!("Failed to reload events: {e}");
+                    }
+                    // Don't wait for the next tick to pick up later edits
+                    reload.reset();
+                }
+                Some(run_event) = self.run_events_rx.recv() => {
+                    self.handle_run_event(run_event).await;
+                }
+                Some(call) = self.calls_rx.recv() => {
+                    if self.handle_call(call).await {
+                        info!("Scheduler stopped on request");
+                        return Ok(());
+                    }
+                }
+                _ = &mut shutdown => {
+                    info!("Scheduler stopped");
+                    return Ok(());
+                }
             }
+        }
+    }
 
-            let now = Utc::now();
-            self.check_and_run_tasks(now).await;
+    /// Wind down after [`Scheduler::run_until`]: queued runs and runs still
+    /// waiting for a free slot are dropped, and runs in progress get up to
+    /// `timeout` to finish before they are interrupted and recorded as such.
+    pub async fn drain(&mut self, timeout: Duration) {
+        self.queued.clear();
+        self.run_slots.close();
+
+        if self.running.is_empty() {
+            return;
+        }
+        info!(
+            "Waiting up to {}s for {} running task(s) to finish",
+            timeout.as_secs(),
+            self.running.values().map(Vec::len).sum::<usize>()
+        );
+
+        let deadline = time::Instant::now() + timeout;
+        let mut interrupted = false;
+        while !self.running.is_empty() {
+            tokio::select! {
+                Some(run_event) = self.run_events_rx.recv() => {
+                    self.handle_run_event(run_event).await;
+                }
+                _ = time::sleep_until(deadline), if !interrupted => {
+                    warn!(
+                        "Drain timeout reached, interrupting {} run(s)",
+                        self.running.values().map(Vec::len).sum::<usize>()
+                    );
+                    for run in self.running.values().flatten() {
+                        run.control.interrupt();
+                    }
+                    interrupted = true;
+                }
+            }
         }
+        info!("All runs finished");
     }
 
-    async fn reload_events(&mut self) -> Result<()> {
-        let storage = Storage::load().await?;
-        let mut schedules = HashMap::new();
+    /// Re-read the task list and apply the difference.
+    ///
+    /// Runs in progress are never touched: they finish with the definition
+    /// they started with. Unchanged tasks keep their in-memory state, removed
+    /// or stopped tasks lose the runs that haven't started yet, and tasks
+    /// whose definition changed are scheduled by the new one from now on.
+    pub async fn reload(&mut self) -> Result<()> {
+        let backend = self.backend().await?;
+        let mut storage = Storage::load_from(&backend).await?;
+        let mut current = self.storage.lock().await;
+
+        let (mut added, mut changed) = (0, 0);
+        for event in &mut storage.events {
+            match current.events.iter().find(|e| e.slug == event.slug) {
+                None => added += 1,
+                Some(old) if same_definition(old, event) => event.clone_from(old),
+                Some(_) => changed += 1,
+            }
+        }
+        let removed = current
+            .events
+            .iter()
+            .filter(|old| !storage.events.iter().any(|e| e.slug == old.slug))
+            .count();
 
+        let mut schedules = HashMap::new();
         for event in &storage.events {
             if event.active {
-                match Schedule::from_str(&event.cron) {
+                match TaskSchedule::for_event(event) {
                     Ok(schedule) => {
                         schedules.insert(event.slug.clone(), schedule);
                     }
                     Err(e) => {
-                        error!(
-                            "Failed to parse cron expression for task '{}': {}",
-                            event.slug, e
-                        );
+                        error!("Failed to load schedule for task '{}': {}", event.slug, e);
                     }
                 }
             }
         }
 
+        // Nothing more to fire for tasks that are gone or stopped
+        self.queued.retain(|slug, _| schedules.contains_key(slug));
+        for (slug, runs) in &self.running {
+            if !schedules.contains_key(slug) {
+                for run in runs.iter().filter(|run| run.pid.is_none()) {
+                    run.unwanted.notify_one();
+                }
+            }
+        }
+        self.last_fired
+            .retain(|slug, _| storage.events.iter().any(|e| &e.slug == slug));
+
+        if added + changed + removed > 0 {
+            info!(
+                "Reloaded tasks: {} added, {} changed, {} removed",
+                added, changed, removed
+            );
+        }
+
         self.schedules = schedules;
-        *self.storage.lock().await = storage;
+        prune_state(&backend, &storage).await;
+        *current = storage;
         Ok(())
     }
 
-    async fn check_and_run_tasks(&self, now: DateTime<Utc>) {
-        let mut storage = self.storage.lock().await;
-        let mut tasks_to_update = Vec::new();
+    async fn check_and_run_tasks(&mut self, now: DateTime<Utc>) {
+        let due: Vec<(Event, Vec<DateTime<Utc>>)> = {
+            let storage = self.storage.lock().await;
+
+            storage
+                .events
+                .iter()
+                .filter_map(|event| self.due_runs(event, now).map(|runs| (event.clone(), runs)))
+                .collect()
+        };
 
-        for (idx, event) in storage.events.iter().enumerate() {
-            if !event.active {
+        for (event, runs) in due {
+            self.last_fired.insert(event.slug.clone(), now);
+
+            if runs.is_empty() {
+                info!("Skipping missed runs of task '{}'", event.slug);
                 continue;
             }
 
-            if let Some(schedule) = self.schedules.get(&event.slug) {
-                if self.should_run(schedule, &event.last_run, now) {
-                    info!("Running task '{}'", event.slug);
-
-                    match self.run_command(&event.command).await {
-                        Ok(output) => {
-                            if output.success {
-                                info!("Task '{}' completed successfully", event.slug);
-                            } else {
-                                error!("Task '{}' failed with exit code", event.slug);
-                            }
-
-                            // Mark task for update
-                            tasks_to_update.push(idx);
-                        }
-                        Err(e) => {
-                            error!("Failed to run task '{}': {}", event.slug, e);
-                        }
-                    }
+            if runs.len() == 1 {
+                self.fire(event, runs[0]);
+                continue;
+            }
+
+            // Catch-up runs go one after another, whatever the concurrency policy
+            info!(
+                "Catching up {} missed runs of task '{}'",
+                runs.len(),
+                event.slug
+            );
+            let running = self.running.contains_key(&event.slug);
+            let queue = self.queued.entry(event.slug.clone()).or_default();
+            queue.extend(runs);
+            if !running {
+                if let Some(scheduled_at) = queue.pop_front() {
+                    self.spawn_run(event, scheduled_at);
                 }
             }
         }
+    }
 
-        // Update last run times for executed tasks
-        let should_save = !tasks_to_update.is_empty();
+    /// Answer a control request; returns whether the scheduler should stop.
+    async fn handle_call(&mut self, call: Call) -> bool {
+        let shutdown = call.request == Request::Shutdown;
+        let response = match self.answer(call.request).await {
+            Ok(reply) => Response::Ok(reply),
+            Err(e) => Response::Error(format!("{e:#}")),
+        };
+        let _ = call.respond.send(response);
+        shutdown
+    }
 
-        for idx in tasks_to_update {
-            storage.events[idx].last_run = Some(now);
+    async fn answer(&mut self, request: Request) -> Result<Reply> {
+        match request {
+            Request::Status => {
+                let storage = self.storage.lock().await;
+                Ok(Reply::Status(DaemonStatus {
+                    pid: std::process::id(),
+                    started_at: self.started_at,
+                    tasks: storage.events.len(),
+                    active_tasks: storage.events.iter().filter(|e| e.active).count(),
+                    running: self.running.values().map(Vec::len).sum(),
+                    queued: self.queued.values().map(VecDeque::len).sum(),
+                    next_fire: storage
+                        .events
+                        .iter()
+                        .filter_map(|event| self.next_run(event))
+                        .min(),
+                }))
+            }
+            Request::List => {
+                let storage = self.storage.lock().await;
+                let tasks = storage
+                    .events
+                    .iter()
+                    .map(|event| TaskStatus {
+                        slug: event.slug.clone(),
+                        cron: event.cron.clone(),
+                        command: event.command.clone(),
+                        active: event.active,
+                        pids: self
+                            .running
+                            .get(&event.slug)
+                            .into_iter()
+                            .flatten()
+                            .filter_map(|run| run.pid)
+                            .collect(),
+                        queued: self.queued.get(&event.slug).map_or(0, VecDeque::len),
+                        last_run: event.last_run,
+                        next_run: self.next_run(event),
+                    })
+                    .collect();
+                Ok(Reply::Tasks(tasks))
+            }
+            Request::Trigger { slug } => {
+                let event = {
+                    let storage = self.storage.lock().await;
+                    storage.events.iter().find(|e| e.slug == slug).cloned()
+                }
+                .ok_or_else(|| anyhow::anyhow!("Task with slug '{}' not found", slug))?;
+
+                info!("Triggering task '{}'", slug);
+                if !self.fire(event, Utc::now()) {
+                    return Err(anyhow::anyhow!(
+                        "Task '{}' is already running and its concurrency policy is skip",
+                        slug
+                    ));
+                }
+                Ok(Reply::Done)
+            }
+            Request::Pause { slugs } => self.set_active(slugs, false).await,
+            Request::Resume { slugs } => self.set_active(slugs, true).await,
+            Request::Reload => {
+                info!("Reloading tasks");
+                self.reload().await?;
+                Ok(Reply::Done)
+            }
+            Request::Shutdown => Ok(Reply::Stopping {
+                pid: std::process::id(),
+            }),
         }
+    }
+
+    /// Mark tasks active or inactive (all of them if `slugs` is empty) in
+    /// storage, then reload to apply it.
+    async fn set_active(&mut self, slugs: Vec<String>, active: bool) -> Result<Reply> {
+        let reply = Storage::update(|storage| {
+            let missing: Vec<String> = slugs
+                .iter()
+                .filter(|slug| !storage.events.iter().any(|e| &e.slug == *slug))
+                .cloned()
+                .collect();
+            let (mut found, mut changed) = (0, 0);
+            for event in &mut storage.events {
+                if slugs.is_empty() || slugs.contains(&event.slug) {
+                    found += 1;
+                    if event.active != active {
+                        event.active = active;
+                        changed += 1;
+                    }
+                }
+            }
+            Ok(Reply::Updated {
+                found,
+                changed,
+                missing,
+            })
+        })
+        .await?;
+
+        self.reload().await?;
+        Ok(reply)
+    }
 
-        // Save storage once after all updates
-        if should_save {
-            if let Err(e) = storage.save().await {
-                error!("Failed to save storage: {e}");
+    /// Fire times of a task to run now, after applying its misfire policy.
+    ///
+    /// `None` means the task is not due. An empty list means it was due but
+    /// every missed fire time is dropped.
+    fn due_runs(&self, event: &Event, now: DateTime<Utc>) -> Option<Vec<DateTime<Utc>>> {
+        let schedule = self.schedules.get(&event.slug)?;
+        let first = self.next_run(event).filter(|next| *next <= now)?;
+
+        // Fire times between the last run and now, keeping only as many as
+        // could be caught up
+        let keep = match event.misfire_policy {
+            MisfirePolicy::RunAllMissed => event.max_missed_runs().max(1),
+            MisfirePolicy::RunOnce | MisfirePolicy::SkipToNext => 1,
+        };
+        let mut missed = VecDeque::with_capacity(keep);
+        for at in std::iter::once(first).chain(schedule.after(first).take_while(|at| *at <= now)) {
+            if missed.len() == keep {
+                missed.pop_front();
             }
+            missed.push_back(at);
+        }
+
+        let grace = chrono::Duration::seconds(event.misfire_grace() as i64);
+        let latest = *missed.back()?;
+        if now - latest <= grace {
+            // On time: run the slot that just came up, and catch up the rest
+            // only if asked to
+            return Some(match event.misfire_policy {
+                MisfirePolicy::RunAllMissed => missed.into(),
+                MisfirePolicy::RunOnce | MisfirePolicy::SkipToNext => vec![latest],
+            });
         }
+
+        info!(
+            "Task '{}' missed its run at {} ({} policy)",
+            event.slug, latest, event.misfire_policy
+        );
+        Some(match event.misfire_policy {
+            MisfirePolicy::RunOnce => vec![latest],
+            MisfirePolicy::RunAllMissed => missed.into(),
+            MisfirePolicy::SkipToNext => Vec::new(),
+        })
     }
 
-    fn should_run(
-        &self,
-        schedule: &Schedule,
-        last_run: &Option<DateTime<Utc>>,
-        now: DateTime<Utc>,
-    ) -> bool {
-        // Get the next scheduled time after the last run (or epoch if never run)
-        let last = last_run.unwrap_or_else(|| DateTime::from_timestamp(0, 0).unwrap());
+    /// Fire every active `@reboot` task once, as the daemon starts.
+    async fn run_reboot_tasks(&mut self, now: DateTime<Utc>) {
+        let reboot: Vec<Event> = {
+            let storage = self.storage.lock().await;
+            storage
+                .events
+                .iter()
+                .filter(|event| event.active)
+                .filter(|event| {
+                    self.schedules
+                        .get(&event.slug)
+                        .is_some_and(TaskSchedule::is_reboot)
+                })
+                .cloned()
+                .collect()
+        };
+
+        for event in reboot {
+            info!("Running @reboot task '{}'", event.slug);
+            self.fire(event, now);
+        }
+    }
+
+    /// Earliest upcoming fire time across all active tasks.
+    async fn next_fire_time(&self) -> Option<DateTime<Utc>> {
+        let storage = self.storage.lock().await;
+        storage
+            .events
+            .iter()
+            .filter_map(|event| self.next_run(event))
+            .min()
+    }
 
-        // Check if there's a scheduled time between last run and now
-        if let Some(next) = schedule.after(&last).next() {
-            // Allow 1 minute tolerance for missed schedules
-            next <= now + chrono::Duration::seconds(30)
-        } else {
-            false
+    /// When an active task is next due, counting from its last run or firing.
+    ///
+    /// A task that never ran counts from its creation, so it waits for its
+    /// first cron slot.
+    fn next_run(&self, event: &Event) -> Option<DateTime<Utc>> {
+        if !event.active {
+            return None;
         }
+
+        let schedule = self.schedules.get(&event.slug)?;
+        let last = event
+            .last_run
+            .max(self.last_fired.get(&event.slug).copied())
+            .unwrap_or(event.created_at);
+
+        schedule.next_after(last)
     }
 
-    async fn run_command(&self, command: &str) -> Result<CommandOutput> {
-        debug!("Executing command: {command}");
+    /// Start a due task, applying its concurrency policy if it is still running.
+    ///
+    /// Returns `false` if the policy dropped the firing.
+    fn fire(&mut self, event: Event, scheduled_at: DateTime<Utc>) -> bool {
+        let active_runs = self.running.get(&event.slug).map_or(0, Vec::len);
+        if active_runs == 0 {
+            self.spawn_run(event, scheduled_at);
+            return true;
+        }
 
-        // Split command into program and args
-        let parts: Vec<&str> = command.split_whitespace().collect();
-        if parts.is_empty() {
-            return Err(anyhow::anyhow!("Empty command"));
+        match event.concurrency_policy {
+            ConcurrencyPolicy::Skip => {
+                info!(
+                    "Skipping task '{}': previous run is still in progress",
+                    event.slug
+                );
+                return false;
+            }
+            ConcurrencyPolicy::Queue => {
+                info!(
+                    "Queueing task '{}' until the previous run finishes",
+                    event.slug
+                );
+                // Several firings during one run collapse into a single queued run
+                let queue = self.queued.entry(event.slug).or_default();
+                if queue.is_empty() {
+                    queue.push_back(scheduled_at);
+                }
+            }
+            ConcurrencyPolicy::Allow => {
+                self.spawn_run(event, scheduled_at);
+            }
+            ConcurrencyPolicy::Replace => {
+                info!("Replacing running instance of task '{}'", event.slug);
+                for run in self.running.get(&event.slug).into_iter().flatten() {
+                    run.control.cancel();
+                    if run.pid.is_none() {
+                        run.unwanted.notify_one();
+                    }
+                }
+                // The new run starts once the cancelled ones have exited, so
+                // the two never overlap
+                let queue = self.queued.entry(event.slug).or_default();
+                queue.clear();
+                queue.push_back(scheduled_at);
+            }
         }
+        true
+    }
 
-        let output = Command::new(parts[0])
-            .args(&parts[1..])
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?
-            .wait_with_output()
-            .await?;
+    /// Run a task on its own tokio task; progress comes back via `run_events_rx`.
+    fn spawn_run(&mut self, event: Event, scheduled_at: DateTime<Utc>) {
+        let token = self.next_token;
+        self.next_token += 1;
+
+        let control = RunControl::new();
+        let unwanted = Arc::new(Notify::new());
+        self.running
+            .entry(event.slug.clone())
+            .or_default()
+            .push(ActiveRun {
+                token,
+                pid: None,
+                control: control.clone(),
+                unwanted: unwanted.clone(),
+            });
+
+        let backend = self.backend.clone();
+        let run_slots = self.run_slots.clone();
+        let run_events_tx = self.run_events_tx.clone();
+
+        tokio::spawn(async move {
+            // The semaphore is only closed when draining; a reload drops runs
+            // of tasks that were removed or stopped meanwhile
+            let permit = tokio::select! {
+                permit = run_slots.acquire_owned() => permit.ok(),
+                _ = unwanted.notified() => None,
+            };
+            let abandon = || {
+                let _ = run_events_tx.send(RunEvent::Abandoned {
+                    slug: event.slug.clone(),
+                    token,
+                });
+            };
+            let Some(_permit) = permit else {
+                return abandon();
+            };
+            let backend = match backend.get_or_try_init(Backend::open).await {
+                Ok(backend) => backend,
+                Err(e) => {
+                    error!("Failed to open storage for task '{}': {e}", event.slug);
+                    return abandon();
+                }
+            };
+
+            info!("Running task '{}'", event.slug);
+            let started_tx = run_events_tx.clone();
+            let record = runner::run(backend, &event, &control, |pid| {
+                let _ = started_tx.send(RunEvent::Started {
+                    slug: event.slug.clone(),
+                    token,
+                    pid,
+                });
+            })
+            .await;
+
+            let _ = run_events_tx.send(RunEvent::Finished {
+                slug: event.slug,
+                token,
+                scheduled_at,
+                record,
+            });
+        });
+    }
 
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
+    async fn handle_run_event(&mut self, run_event: RunEvent) {
+        match run_event {
+            RunEvent::Started { slug, token, pid } => {
+                if let Some(run) = self
+                    .running
+                    .get_mut(&slug)
+                    .and_then(|runs| runs.iter_mut().find(|r| r.token == token))
+                {
+                    run.pid = Some(pid);
+                }
+                self.update_state(&slug, |task| task.pid = Some(pid)).await;
+            }
+            RunEvent::Finished {
+                slug,
+                token,
+                scheduled_at,
+                record,
+            } => {
+                self.handle_finished(slug, token, scheduled_at, record)
+                    .await;
+            }
+            RunEvent::Abandoned { slug, token } => {
+                info!("Dropping pending run of task '{}'", slug);
+                if let Some(runs) = self.running.get_mut(&slug) {
+                    runs.retain(|r| r.token != token);
+                    if runs.is_empty() {
+                        self.running.remove(&slug);
+                    }
+                }
+                self.start_queued(&slug).await;
+            }
+        }
+    }
 
-        if !stdout.is_empty() {
-            debug!("Command stdout: {stdout}");
+    async fn handle_finished(
+        &mut self,
+        slug: String,
+        token: u64,
+        scheduled_at: DateTime<Utc>,
+        record: RunRecord,
+    ) {
+        match record.status {
+            RunStatus::Succeeded => {
+                info!("Task '{}' completed successfully", slug);
+            }
+            _ => match &record.error {
+                Some(e) => error!("Failed to run task '{}': {}", slug, e),
+                None => error!(
+                    "Task '{}' {} (exit code: {:?}, signal: {:?})",
+                    slug, record.status, record.exit_code, record.signal
+                ),
+            },
         }
-        if !stderr.is_empty() {
-            debug!("Command stderr: {stderr}");
+
+        // Keep pointing `pid` at a run that is still alive, if any
+        let remaining_pid = match self.running.get_mut(&slug) {
+            Some(runs) => {
+                runs.retain(|r| r.token != token);
+                runs.iter().rev().find_map(|r| r.pid)
+            }
+            None => None,
+        };
+        if self.running.get(&slug).is_some_and(Vec::is_empty) {
+            self.running.remove(&slug);
         }
 
-        Ok(CommandOutput {
-            success: output.status.success(),
-            _stdout: stdout.to_string(),
-            _stderr: stderr.to_string(),
+        let next_run = self
+            .schedules
+            .get(&slug)
+            .and_then(|schedule| schedule.next_after(Utc::now()));
+        self.update_state(&slug, |task| {
+            task.last_run = task.last_run.max(Some(scheduled_at));
+            task.next_run = next_run;
+            task.pid = remaining_pid;
+            match record.status {
+                RunStatus::Succeeded => task.consecutive_failures = 0,
+                // Stopped by the scheduler, not a fault of the task
+                RunStatus::Cancelled | RunStatus::Interrupted => {}
+                _ => {
+                    task.consecutive_failures += 1;
+                    task.failures += 1;
+                }
+            }
         })
+        .await;
+
+        self.start_queued(&slug).await;
+    }
+
+    /// Start the next queued run of a task once none of its runs are left.
+    async fn start_queued(&mut self, slug: &str) {
+        if self.running.contains_key(slug) {
+            return;
+        }
+        let queued_at = self.queued.get_mut(slug).and_then(VecDeque::pop_front);
+        if self.queued.get(slug).is_some_and(VecDeque::is_empty) {
+            self.queued.remove(slug);
+        }
+        if let Some(queued_at) = queued_at {
+            let event = {
+                let storage = self.storage.lock().await;
+                storage
+                    .events
+                    .iter()
+                    .find(|e| e.slug == slug && e.active)
+                    .cloned()
+            };
+            match event {
+                Some(event) => {
+                    info!("Starting queued run of task '{}'", slug);
+                    self.spawn_run(event, queued_at);
+                }
+                // Removed or stopped since, so drop the rest of its queue
+                None => {
+                    self.queued.remove(slug);
+                }
+            }
+        }
+    }
+
+    /// Apply a change to a task's runtime state and persist it; no-op if the
+    /// task was removed.
+    ///
+    /// Only `state.json` is written, so the CLI's edits to task definitions
+    /// are never touched.
+    async fn update_state(&self, slug: &str, change: impl FnOnce(&mut TaskState)) {
+        let mut storage = self.storage.lock().await;
+        let Some(event) = storage.events.iter_mut().find(|e| e.slug == slug) else {
+            return;
+        };
+
+        let result = match self.backend().await {
+            Ok(backend) => {
+                State::update_in(&backend, |state| {
+                    let task = state.tasks.entry(slug.to_string()).or_default();
+                    change(task);
+                    Ok(task.clone())
+                })
+                .await
+            }
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(task) => task.apply_to(event),
+            Err(e) => error!("Failed to save state: {e}"),
+        }
+    }
+}
+
+/// Whether two versions of a task have the same definition, ignoring the
+/// runtime state merged into them.
+fn same_definition(a: &Event, b: &Event) -> bool {
+    let runtime = |event: &Event| Event {
+        pid: None,
+        last_run: None,
+        ..event.clone()
+    };
+    runtime(a) == runtime(b)
+}
+
+/// Drop runtime state left behind by tasks that no longer exist.
+async fn prune_state(backend: &Backend, storage: &Storage) {
+    let slugs: HashSet<&str> = storage.events.iter().map(|e| e.slug.as_str()).collect();
+
+    match backend.load_state().await {
+        Ok(state)
+            if state
+                .tasks
+                .keys()
+                .any(|slug| !slugs.contains(slug.as_str())) => {}
+        Ok(_) => return,
+        Err(e) => {
+            error!("Failed to load state: {e}");
+            return;
+        }
+    }
+
+    let result = State::update_in(backend, |state| {
+        state.tasks.retain(|slug, _| slugs.contains(slug.as_str()));
+        Ok(())
+    })
+    .await;
+    if let Err(e) = result {
+        error!("Failed to prune state: {e}");
     }
 }
 
-struct CommandOutput {
-    success: bool,
-    _stdout: String,
-    _stderr: String,
+/// Wait for the next watchdog ping, or forever if there is no watchdog.
+async fn watchdog_due(watchdog: &mut Option<(Arc<Notifier>, time::Interval)>) -> Arc<Notifier> {
+    let Some((notifier, pings)) = watchdog else {
+        return std::future::pending().await;
+    };
+
+    pings.tick().await;
+    notifier.clone()
+}
+
+/// Sleep until the given wall-clock time, or forever if there is none.
+async fn sleep_until(at: Option<DateTime<Utc>>) {
+    let Some(at) = at else {
+        return std::future::pending().await;
+    };
+
+    // Already past due times come back as an error and mean "now"
+    let delay = (at - Utc::now()).to_std().unwrap_or(Duration::ZERO);
+    time::sleep(delay).await;
 }
@@ -0,0 +1,105 @@
+use anyhow::{Context, Result};
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+/// Socket a service manager listens on for readiness messages.
+pub const NOTIFY_SOCKET_ENV: &str = "NOTIFY_SOCKET";
+/// Watchdog timeout in microseconds, when the manager expects pings.
+const WATCHDOG_USEC_ENV: &str = "WATCHDOG_USEC";
+/// Process the watchdog timeout is meant for, if set.
+const WATCHDOG_PID_ENV: &str = "WATCHDOG_PID";
+
+/// Sends `sd_notify` messages (`READY=1`, `STOPPING=1`, `WATCHDOG=1`, ...) to
+/// systemd or any other manager implementing the protocol.
+#[derive(Debug)]
+pub struct Notifier {
+    socket: UnixDatagram,
+    address: std::os::unix::net::SocketAddr,
+    watchdog: Option<Duration>,
+}
+
+impl Notifier {
+    /// A notifier for `$NOTIFY_SOCKET`, or `None` when it isn't set; see
+    /// [`remove_env`] for keeping the variables from tasks.
+    pub fn from_env() -> Result<Option<Self>> {
+        let path = std::env::var(NOTIFY_SOCKET_ENV).ok();
+        let watchdog = parse_watchdog(
+            std::env::var(WATCHDOG_USEC_ENV).ok().as_deref(),
+            std::env::var(WATCHDOG_PID_ENV).ok().as_deref(),
+            std::process::id(),
+        );
+        match path {
+            Some(path) if !path.is_empty() => {
+                let mut notifier = Self::new(&path)?;
+                notifier.watchdog = watchdog;
+                Ok(Some(notifier))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// A notifier for the socket at `path`; a leading `@` names a socket in
+    /// the abstract namespace.
+    pub fn new(path: &str) -> Result<Self> {
+        let address = match path.strip_prefix('@') {
+            Some(name) => abstract_address(name)?,
+            None => std::os::unix::net::SocketAddr::from_pathname(path)?,
+        };
+        let socket = UnixDatagram::unbound()?;
+        Ok(Notifier {
+            socket,
+            address,
+            watchdog: None,
+        })
+    }
+
+    /// How often to send `WATCHDOG=1`: half the interval the manager asked
+    /// for in `$WATCHDOG_USEC`, if it is meant for this process.
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog
+    }
+
+    /// Send one message, e.g. `READY=1`; several assignments may be joined
+    /// with newlines.
+    pub fn notify(&self, state: &str) -> Result<()> {
+        self.socket
+            .send_to_addr(state.as_bytes(), &self.address)
+            .with_context(|| format!("Failed to send '{state}' to ${NOTIFY_SOCKET_ENV}"))?;
+        Ok(())
+    }
+}
+
+/// Remove the variables [`Notifier::from_env`] reads, so tasks don't inherit
+/// them and report for the scheduler.
+///
+/// Changing the environment is only safe while the process is still
+/// single-threaded, so call this before any runtime is built.
+pub fn remove_env() {
+    for name in [NOTIFY_SOCKET_ENV, WATCHDOG_USEC_ENV, WATCHDOG_PID_ENV] {
+        std::env::remove_var(name);
+    }
+}
+
+/// The ping interval for the given `WATCHDOG_USEC` and `WATCHDOG_PID` values.
+pub fn parse_watchdog(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    if let Some(pid) = pid {
+        if pid.parse::<u32>().ok()? != own_pid {
+            return None;
+        }
+    }
+    let usec = usec?.parse::<u64>().ok().filter(|usec| *usec > 0)?;
+    Some(Duration::from_micros(usec) / 2)
+}
+
+#[cfg(target_os = "linux")]
+fn abstract_address(name: &str) -> Result<std::os::unix::net::SocketAddr> {
+    use std::os::linux::net::SocketAddrExt;
+    Ok(std::os::unix::net::SocketAddr::from_abstract_name(name)?)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn abstract_address(name: &str) -> Result<std::os::unix::net::SocketAddr> {
+    Err(anyhow::anyhow!(
+        "Abstract socket @{name} is only supported on Linux"
+    ))
+}
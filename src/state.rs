@@ -0,0 +1,73 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::backend::{Backend, StorageBackend};
+use crate::storage::Event;
+
+/// What the daemon knows about a task at runtime.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TaskState {
+    /// Fire time of the most recent completed run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<DateTime<Utc>>,
+    /// When the task is next scheduled to fire.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_run: Option<DateTime<Utc>>,
+    /// PID of the live child while a run is in progress.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    /// Failed runs since the last successful one.
+    #[serde(default)]
+    pub consecutive_failures: u32,
+    /// Failed runs over the task's lifetime.
+    #[serde(default)]
+    pub failures: u64,
+}
+
+impl TaskState {
+    /// Copy the runtime fields mirrored on `Event` onto it.
+    pub fn apply_to(&self, event: &mut Event) {
+        event.last_run = self.last_run;
+        event.pid = self.pid;
+    }
+}
+
+/// Runtime state of all tasks, kept in `~/.singleschedule/state.json` by the
+/// JSON backend.
+///
+/// Only the daemon writes this file, so task definitions in `events.json`
+/// stay free of per-run noise.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    pub tasks: BTreeMap<String, TaskState>,
+}
+
+impl State {
+    // The daemon goes through its own backend; this and `update` are for
+    // library users and tests
+    #[allow(dead_code)]
+    pub async fn load() -> Result<Self> {
+        Backend::open().await?.load_state().await
+    }
+
+    /// Re-read the state, apply `change` and save it under the storage lock.
+    #[allow(dead_code)]
+    pub async fn update<T>(change: impl FnOnce(&mut State) -> Result<T>) -> Result<T> {
+        Self::update_in(&Backend::open().await?, change).await
+    }
+
+    /// Like [`State::update`], in a given backend.
+    pub async fn update_in<T>(
+        backend: &impl StorageBackend,
+        change: impl FnOnce(&mut State) -> Result<T>,
+    ) -> Result<T> {
+        let _lock = backend.lock().await?;
+
+        let mut state = backend.load_state().await?;
+        let result = change(&mut state)?;
+        backend.save_state(&state).await?;
+        Ok(result)
+    }
+}
@@ -1,25 +1,189 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::Path;
 use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use crate::backend::{Backend, StorageBackend};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Event {
     pub slug: String,
     pub cron: String,
     pub command: String,
+    /// PID of the live child while a run is in progress.
+    ///
+    /// Runtime state: loaded from [`crate::state::State`] and never saved with
+    /// the task definitions (older files that still contain it are migrated).
+    #[serde(default, skip_serializing)]
     pub pid: Option<u32>,
     pub created_at: DateTime<Utc>,
+    /// Fire time of the most recent completed run; runtime state like `pid`.
+    #[serde(default, skip_serializing)]
     pub last_run: Option<DateTime<Utc>>,
     #[serde(default = "default_active")]
     pub active: bool,
+    /// Exact arguments given after `--`, executed without any re-splitting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub argv: Option<Vec<String>>,
+    /// Shell used to run `command` as `<shell> -c <command>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    /// Number of run records kept for this task (defaults to `DEFAULT_HISTORY_LIMIT`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history_limit: Option<usize>,
+    /// Seconds a run may take before its process group is terminated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    /// What to do when the task fires while a previous run is still going.
+    #[serde(default)]
+    pub concurrency_policy: ConcurrencyPolicy,
+    /// What to do about fire times that were missed, e.g. while the daemon was down.
+    #[serde(default)]
+    pub misfire_policy: MisfirePolicy,
+    /// Most missed runs caught up under `run_all_missed` (defaults to `DEFAULT_MAX_MISSED_RUNS`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_missed_runs: Option<usize>,
+    /// Seconds a fire time may be late and still run as scheduled
+    /// (defaults to `DEFAULT_MISFIRE_GRACE`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub misfire_grace: Option<u64>,
+    /// IANA timezone the cron expression is evaluated in (UTC when unset).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ConcurrencyPolicy {
+    /// Drop the new run
+    #[default]
+    Skip,
+    /// Start the new run once the current one finishes
+    Queue,
+    /// Start the new run alongside the current one
+    Allow,
+    /// Terminate the current run and start the new one
+    Replace,
+}
+
+impl std::fmt::Display for ConcurrencyPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ConcurrencyPolicy::Skip => "skip",
+            ConcurrencyPolicy::Queue => "queue",
+            ConcurrencyPolicy::Allow => "allow",
+            ConcurrencyPolicy::Replace => "replace",
+        };
+        f.pad(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum MisfirePolicy {
+    /// Run once in place of all missed fire times
+    #[default]
+    RunOnce,
+    /// Run once for every missed fire time, up to the task's limit
+    RunAllMissed,
+    /// Drop missed fire times and wait for the next one
+    SkipToNext,
+}
+
+impl std::fmt::Display for MisfirePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            MisfirePolicy::RunOnce => "run_once",
+            MisfirePolicy::RunAllMissed => "run_all_missed",
+            MisfirePolicy::SkipToNext => "skip_to_next",
+        };
+        f.pad(name)
+    }
 }
 
 fn default_active() -> bool {
     true
 }
 
+/// Shell used when shell mode is requested without naming one.
+pub const DEFAULT_SHELL: &str = "/bin/sh";
+
+/// Run records kept per task when `history_limit` is not set.
+pub const DEFAULT_HISTORY_LIMIT: usize = 50;
+
+/// Missed runs caught up per task when `max_missed_runs` is not set.
+pub const DEFAULT_MAX_MISSED_RUNS: usize = 10;
+
+/// Seconds a fire time may be late when `misfire_grace` is not set.
+pub const DEFAULT_MISFIRE_GRACE: u64 = 30;
+
+impl Default for Event {
+    fn default() -> Self {
+        Event {
+            slug: String::new(),
+            cron: String::new(),
+            command: String::new(),
+            pid: None,
+            created_at: Utc::now(),
+            last_run: None,
+            active: true,
+            argv: None,
+            shell: None,
+            history_limit: None,
+            timeout: None,
+            concurrency_policy: ConcurrencyPolicy::default(),
+            misfire_policy: MisfirePolicy::default(),
+            max_missed_runs: None,
+            misfire_grace: None,
+            timezone: None,
+        }
+    }
+}
+
+impl Event {
+    /// Resolve the program and arguments to execute for this task.
+    ///
+    /// Shell mode wins over `argv`; events written before either existed fall
+    /// back to splitting `command` on whitespace.
+    pub fn program_and_args(&self) -> Result<(String, Vec<String>)> {
+        if let Some(shell) = &self.shell {
+            if self.command.trim().is_empty() {
+                return Err(anyhow::anyhow!("Empty command"));
+            }
+            return Ok((shell.clone(), vec!["-c".to_string(), self.command.clone()]));
+        }
+
+        let parts: Vec<String> = match &self.argv {
+            Some(argv) => argv.clone(),
+            None => self
+                .command
+                .split_whitespace()
+                .map(str::to_string)
+                .collect(),
+        };
+
+        let mut parts = parts.into_iter();
+        let program = parts
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Empty command"))?;
+        Ok((program, parts.collect()))
+    }
+
+    pub fn history_limit(&self) -> usize {
+        self.history_limit.unwrap_or(DEFAULT_HISTORY_LIMIT)
+    }
+
+    pub fn max_missed_runs(&self) -> usize {
+        self.max_missed_runs.unwrap_or(DEFAULT_MAX_MISSED_RUNS)
+    }
+
+    pub fn misfire_grace(&self) -> u64 {
+        self.misfire_grace.unwrap_or(DEFAULT_MISFIRE_GRACE)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Storage {
     pub events: Vec<Event>,
@@ -36,50 +200,118 @@ impl Storage {
         Storage { events: Vec::new() }
     }
 
+    /// Load the task definitions together with their runtime state.
     pub async fn load() -> Result<Self> {
-        let path = Self::get_path()?;
+        Self::load_from(&Backend::open().await?).await
+    }
+
+    /// Like [`Storage::load`], from a given backend.
+    pub async fn load_from(backend: &impl StorageBackend) -> Result<Self> {
+        let tasks = {
+            let _lock = backend.lock().await?;
+            backend.load_tasks().await?
+        };
+        tasks.with_state(backend).await
+    }
 
-        if !path.exists() {
-            // Create directory if it doesn't exist
-            if let Some(parent) = path.parent() {
-                fs::create_dir_all(parent).await?;
+    /// Fill in each task's runtime fields from the stored runtime state.
+    async fn with_state(mut self, backend: &impl StorageBackend) -> Result<Self> {
+        let state = backend.load_state().await?;
+        for event in &mut self.events {
+            if let Some(task) = state.tasks.get(&event.slug) {
+                task.apply_to(event);
             }
-            return Ok(Self::new());
         }
-
-        let content = fs::read_to_string(&path).await?;
-        let storage: Storage = serde_json::from_str(&content)?;
-        Ok(storage)
+        Ok(self)
     }
 
+    /// Save the task list, replacing whatever is stored.
+    ///
+    /// To change part of the task list use [`Storage::update`] so edits from
+    /// other processes are not lost.
+    // The binary only goes through `update`; this is for library users and tests
+    #[allow(dead_code)]
     pub async fn save(&self) -> Result<()> {
-        let path = Self::get_path()?;
+        let backend = Backend::open().await?;
+        let _lock = backend.lock().await?;
+        backend.save_tasks(self).await
+    }
 
-        // Create directory if it doesn't exist
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent).await?;
+    /// Re-read the task list, apply `change` and save it, all while holding
+    /// the cross-process storage lock.
+    ///
+    /// Nothing is written when `change` returns an error.
+    pub async fn update<T>(change: impl FnOnce(&mut Storage) -> Result<T>) -> Result<T> {
+        let backend = Backend::open().await?;
+        let _lock = backend.lock().await?;
+
+        let mut storage = backend.load_tasks().await?.with_state(&backend).await?;
+        let result = change(&mut storage)?;
+        backend.save_tasks(&storage).await?;
+        Ok(result)
+    }
+
+    /// Like [`Storage::update`], then refresh `self` with the saved task list.
+    pub async fn update_in_place<T>(
+        &mut self,
+        change: impl FnOnce(&mut Storage) -> Result<T>,
+    ) -> Result<T> {
+        let (result, events) = Self::update(|storage| {
+            let result = change(storage)?;
+            Ok((result, storage.events.clone()))
+        })
+        .await?;
+
+        self.events = events;
+        Ok(result)
+    }
+
+    /// Add a new task, refusing a slug that is already taken.
+    pub fn insert_event(&mut self, event: Event) -> Result<()> {
+        if self.events.iter().any(|e| e.slug == event.slug) {
+            return Err(anyhow::anyhow!(
+                "Task with slug '{}' already exists",
+                event.slug
+            ));
         }
 
-        let content = serde_json::to_string_pretty(self)?;
-        fs::write(&path, content).await?;
+        self.events.push(event);
         Ok(())
     }
+}
 
-    fn get_path() -> Result<PathBuf> {
-        #[cfg(test)]
-        {
-            if let Ok(test_home) = std::env::var("SINGLESCHEDULE_TEST_HOME") {
-                return Ok(PathBuf::from(test_home)
-                    .join(".singleschedule")
-                    .join("events.json"));
-            }
-        }
+/// Replace a file's contents so that readers see either the old or the new
+/// version, even if we crash halfway: write a temp file, fsync it, rename it
+/// over the target and fsync the directory.
+pub async fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("{} has no parent directory", path.display()))?;
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("{} has no file name", path.display()))?;
 
-        let home = directories::UserDirs::new()
-            .ok_or_else(|| anyhow::anyhow!("Failed to get home directory"))?
-            .home_dir()
-            .to_path_buf();
+    // Per-process temp name so the CLI and the daemon never share one
+    let tmp_path = dir.join(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+
+    let result = async {
+        let mut file = fs::File::create(&tmp_path).await?;
+        file.write_all(contents).await?;
+        file.sync_all().await?;
+        drop(file);
+
+        fs::rename(&tmp_path, path).await?;
+        fs::File::open(dir).await?.sync_all().await?;
+        Ok(())
+    };
 
-        Ok(home.join(".singleschedule").join("events.json"))
+    let result: Result<()> = result.await;
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path).await;
     }
+    result
 }
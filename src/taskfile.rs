@@ -0,0 +1,301 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use tokio::fs;
+
+use crate::schedule::TaskSchedule;
+use crate::storage::{ConcurrencyPolicy, Event, MisfirePolicy, Storage, DEFAULT_SHELL};
+
+/// Tasks declared in a TOML file, e.g. one kept in version control, as a
+/// list of `[[task]]` tables.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaskFile {
+    #[serde(default, rename = "task")]
+    pub tasks: Vec<TaskSpec>,
+}
+
+/// One `[[task]]` entry, taking the same settings as `add`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaskSpec {
+    pub slug: String,
+    pub cron: String,
+    pub command: CommandSpec,
+    /// Shell for a string `command`; `/bin/sh` when not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    /// Left as it is when omitted, so `start` and `stop` keep working on
+    /// tasks managed by a file. New tasks start out active.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history_limit: Option<usize>,
+    /// Seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub concurrency_policy: ConcurrencyPolicy,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub misfire_policy: MisfirePolicy,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_missed_runs: Option<usize>,
+    /// Seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub misfire_grace: Option<u64>,
+}
+
+/// A task's command: arguments executed directly, or a script run by a shell.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CommandSpec {
+    Argv(Vec<String>),
+    Script(String),
+}
+
+/// What `apply` would do to one task.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Add(Event),
+    Update {
+        event: Event,
+        fields: Vec<FieldChange>,
+    },
+    Remove(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// Changes bringing the stored tasks in line with a task file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Plan {
+    pub changes: Vec<Change>,
+    /// Stored tasks missing from the file that stay because pruning is off.
+    pub kept: Vec<String>,
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+impl TaskFile {
+    pub async fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&content).with_context(|| format!("Invalid task file {}", path.display()))
+    }
+
+    /// Parse and validate a task file.
+    pub fn parse(content: &str) -> Result<Self> {
+        let file: TaskFile = toml::from_str(content)?;
+
+        let mut slugs = HashSet::new();
+        for spec in &file.tasks {
+            if !slugs.insert(spec.slug.as_str()) {
+                return Err(anyhow::anyhow!("Task '{}' is declared twice", spec.slug));
+            }
+            spec.to_event()?;
+        }
+
+        Ok(file)
+    }
+
+    /// Describe the stored tasks in task file form.
+    pub fn from_storage(storage: &Storage) -> Self {
+        TaskFile {
+            tasks: storage.events.iter().map(TaskSpec::from_event).collect(),
+        }
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    /// Work out how to bring `storage` in line with this file. Tasks missing
+    /// from the file are only removed with `prune`.
+    pub fn plan(&self, storage: &Storage, prune: bool) -> Result<Plan> {
+        let mut plan = Plan::default();
+
+        for spec in &self.tasks {
+            let mut event = spec.to_event()?;
+            let Some(current) = storage.events.iter().find(|e| e.slug == spec.slug) else {
+                plan.changes.push(Change::Add(event));
+                continue;
+            };
+
+            // Keep what isn't part of the definition
+            event.created_at = current.created_at;
+            event.pid = current.pid;
+            event.last_run = current.last_run;
+            if spec.active.is_none() {
+                event.active = current.active;
+            }
+
+            let fields = field_changes(current, &event)?;
+            if !fields.is_empty() {
+                plan.changes.push(Change::Update { event, fields });
+            }
+        }
+
+        for event in &storage.events {
+            if self.tasks.iter().any(|spec| spec.slug == event.slug) {
+                continue;
+            }
+            if prune {
+                plan.changes.push(Change::Remove(event.slug.clone()));
+            } else {
+                plan.kept.push(event.slug.clone());
+            }
+        }
+
+        Ok(plan)
+    }
+}
+
+impl Plan {
+    pub fn apply(&self, storage: &mut Storage) {
+        for change in &self.changes {
+            match change {
+                Change::Add(event) => storage.events.push(event.clone()),
+                Change::Update { event, .. } => {
+                    if let Some(current) = storage.events.iter_mut().find(|e| e.slug == event.slug)
+                    {
+                        *current = event.clone();
+                    }
+                }
+                Change::Remove(slug) => storage.events.retain(|e| &e.slug != slug),
+            }
+        }
+    }
+}
+
+impl TaskSpec {
+    /// The task this entry describes, validated like `add` would.
+    pub fn to_event(&self) -> Result<Event> {
+        if self.slug.trim().is_empty() {
+            return Err(anyhow::anyhow!("Task slug cannot be empty"));
+        }
+        TaskSchedule::new(&self.cron, self.timezone.as_deref())
+            .with_context(|| format!("Task '{}'", self.slug))?;
+
+        // A string is a shell script; a list is executed as is unless a shell
+        // is named, in which case its words form the script, as with `add`
+        let (command, argv, shell) = match &self.command {
+            CommandSpec::Script(script) => (
+                script.clone(),
+                None,
+                Some(
+                    self.shell
+                        .clone()
+                        .unwrap_or_else(|| DEFAULT_SHELL.to_string()),
+                ),
+            ),
+            CommandSpec::Argv(argv) => match &self.shell {
+                Some(shell) => (argv.join(" "), None, Some(shell.clone())),
+                None => (argv.join(" "), Some(argv.clone()), None),
+            },
+        };
+        if command.trim().is_empty() {
+            return Err(anyhow::anyhow!("Task '{}' has an empty command", self.slug));
+        }
+
+        Ok(Event {
+            slug: self.slug.clone(),
+            cron: self.cron.clone(),
+            command,
+            active: self.active.unwrap_or(true),
+            argv,
+            shell,
+            history_limit: self.history_limit,
+            timeout: self.timeout,
+            concurrency_policy: self.concurrency_policy,
+            misfire_policy: self.misfire_policy,
+            max_missed_runs: self.max_missed_runs,
+            misfire_grace: self.misfire_grace,
+            timezone: self.timezone.clone(),
+            ..Default::default()
+        })
+    }
+
+    pub fn from_event(event: &Event) -> Self {
+        let (command, shell) = match (&event.shell, &event.argv) {
+            (Some(shell), _) => (
+                CommandSpec::Script(event.command.clone()),
+                Some(shell.clone()).filter(|shell| shell != DEFAULT_SHELL),
+            ),
+            (None, Some(argv)) => (CommandSpec::Argv(argv.clone()), None),
+            // Written before `argv` existed; run split on whitespace
+            (None, None) => (
+                CommandSpec::Argv(
+                    event
+                        .command
+                        .split_whitespace()
+                        .map(str::to_string)
+                        .collect(),
+                ),
+                None,
+            ),
+        };
+
+        TaskSpec {
+            slug: event.slug.clone(),
+            cron: event.cron.clone(),
+            command,
+            shell,
+            // Active is the default for new tasks, so only a stop is recorded
+            active: Some(false).filter(|_| !event.active),
+            timezone: event.timezone.clone(),
+            history_limit: event.history_limit,
+            timeout: event.timeout,
+            concurrency_policy: event.concurrency_policy,
+            misfire_policy: event.misfire_policy,
+            max_missed_runs: event.max_missed_runs,
+            misfire_grace: event.misfire_grace,
+        }
+    }
+}
+
+/// Settings that differ between two versions of a task, compared in task
+/// file form so only the definition counts.
+fn field_changes(old: &Event, new: &Event) -> Result<Vec<FieldChange>> {
+    let mut old_spec = TaskSpec::from_event(old);
+    let mut new_spec = TaskSpec::from_event(new);
+    // Spell out the active flag, which task file form leaves implicit
+    old_spec.active = Some(old.active);
+    new_spec.active = Some(new.active);
+
+    let serde_json::Value::Object(old_fields) = serde_json::to_value(&old_spec)? else {
+        unreachable!("a task spec serializes to a map");
+    };
+    let serde_json::Value::Object(new_fields) = serde_json::to_value(&new_spec)? else {
+        unreachable!("a task spec serializes to a map");
+    };
+
+    let mut names: Vec<&String> = old_fields.keys().chain(new_fields.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    let show = |value: Option<&serde_json::Value>| match value {
+        Some(value) => value.to_string(),
+        None => "(unset)".to_string(),
+    };
+
+    Ok(names
+        .into_iter()
+        .filter(|name| old_fields.get(*name) != new_fields.get(*name))
+        .map(|name| FieldChange {
+            field: name.clone(),
+            old: show(old_fields.get(name)),
+            new: show(new_fields.get(name)),
+        })
+        .collect())
+}
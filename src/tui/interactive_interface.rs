@@ -161,9 +161,9 @@ async fn task_actions(storage: &mut Storage, task_index: usize) -> Result<()> {
             storage.save().await?;
             println!("✅ Task '{}' {new_status}!", storage.events[task_index].slug);
             
-            // Restart daemon
-            if let Err(e) = crate::daemon::restart_daemon().await {
-                println!("⚠️  Warning: Failed to restart daemon: {e}");
+            // Reload daemon
+            if let Err(e) = crate::daemon::reload_daemon().await {
+                println!("⚠️  Warning: Failed to reload daemon: {e}");
             }
         }
         "🗑️  Delete Task" => {
@@ -185,10 +185,10 @@ async fn task_actions(storage: &mut Storage, task_index: usize) -> Result<()> {
                 storage.save().await?;
                 println!("✅ Task '{}' deleted successfully!", removed_task.slug);
                 
-                // Restart daemon if needed
+                // Reload daemon if needed
                 if storage.events.iter().any(|e| e.active) {
-                    if let Err(e) = crate::daemon::restart_daemon().await {
-                        println!("⚠️  Warning: Failed to restart daemon: {e}");
+                    if let Err(e) = crate::daemon::reload_daemon().await {
+                        println!("⚠️  Warning: Failed to reload daemon: {e}");
                     }
                 } else if let Err(e) = crate::daemon::stop_daemon().await {
                     println!("⚠️  Warning: Failed to stop daemon: {e}");
@@ -280,9 +280,9 @@ async fn add_task_interactive(storage: &mut Storage) -> Result<()> {
     
     println!("✅ Task '{slug}' added successfully!");
     
-    // Restart daemon
-    if let Err(e) = crate::daemon::restart_daemon().await {
-        println!("⚠️  Warning: Failed to restart daemon: {e}");
+    // Reload daemon
+    if let Err(e) = crate::daemon::reload_daemon().await {
+        println!("⚠️  Warning: Failed to reload daemon: {e}");
     }
     
     println!("\nPress Enter to continue...");
@@ -1,4 +1,5 @@
-use crate::storage::{Event, Storage};
+use crate::schedule;
+use crate::storage::{Event, Storage, DEFAULT_SHELL};
 use anyhow::Result;
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 use r3bl_tui::{
@@ -148,12 +149,13 @@ async fn run_simple_interface(storage: &mut Storage) -> Result<()> {
             for (index, event) in storage.events.iter().enumerate() {
                 let status = if event.active { "✅" } else { "⏸️" };
                 println!(
-                    "{:2}. {} {:<20} {:<15} {}",
+                    "{:2}. {} {:<20} {:<15} {:<25} {}",
                     index + 1,
                     status,
                     truncate(&event.slug, 20),
                     truncate(&event.cron, 15),
-                    truncate(&event.command, 25)
+                    truncate(&event.command, 25),
+                    run_times(event)
                 );
             }
             println!("{}\n", "-".repeat(60));
@@ -361,12 +363,13 @@ fn create_task_list_display(storage: &Storage) -> InlineVec<InlineVec<AnsiStyled
         for (index, event) in storage.events.iter().enumerate() {
             let status = if event.active { "✅" } else { "⏸️" };
             let task_line = format!(
-                "{:2}. {} {:<20} {:<15} {}",
+                "{:2}. {} {:<20} {:<15} {:<25} {}",
                 index + 1,
                 status,
                 truncate(&event.slug, 20),
                 truncate(&event.cron, 15),
-                truncate(&event.command, 25)
+                truncate(&event.command, 25),
+                run_times(event)
             );
 
             let task_ast = ast(
@@ -547,6 +550,10 @@ async fn add_task_with_readline(
             "• Command to execute",
             new_style!(color_fg: {tui_color!(200, 200, 200)}),
         )],
+        inline_vec![ast(
+            "• Timeout (optional, e.g., '30s' or '5m')",
+            new_style!(color_fg: {tui_color!(200, 200, 200)}),
+        )],
         inline_vec![]
     ];
 
@@ -601,8 +608,8 @@ async fn add_task_with_readline(
     let cron = cron.trim().to_string();
 
     // Validate cron
-    if let Err(e) = cron::Schedule::from_str(&cron) {
-        println!("Error: Invalid cron expression: {e}");
+    if let Err(e) = schedule::TaskSchedule::new(&cron, None) {
+        println!("Error: {e}");
         std::thread::sleep(std::time::Duration::from_secs(2));
         return Ok(());
     }
@@ -620,25 +627,52 @@ async fn add_task_with_readline(
         return Ok(());
     }
 
+    // Get optional timeout
+    print!("Enter timeout (e.g., '30s', '5m'; empty for none): ");
+    std::io::Write::flush(&mut std::io::stdout())?;
+    let mut timeout = String::new();
+    std::io::stdin().read_line(&mut timeout)?;
+    let timeout = timeout.trim();
+
+    let timeout = if timeout.is_empty() {
+        None
+    } else {
+        match crate::cli::parse_duration(timeout) {
+            Ok(secs) => Some(secs),
+            Err(e) => {
+                println!("Error: {e}");
+                std::thread::sleep(std::time::Duration::from_secs(2));
+                return Ok(());
+            }
+        }
+    };
+
     // Create and save task
     let event = Event {
         slug: slug.clone(),
         cron,
         command,
-        pid: None,
-        created_at: chrono::Utc::now(),
-        last_run: None,
-        active: true,
+        // Commands typed into the TUI are command lines, so let the shell parse them
+        shell: Some(DEFAULT_SHELL.to_string()),
+        timeout,
+        ..Default::default()
     };
 
-    storage.events.push(event);
-    storage.save().await?;
+    // Re-checked under the storage lock in case another process added it
+    if let Err(e) = storage
+        .update_in_place(|storage| storage.insert_event(event))
+        .await
+    {
+        println!("Error: {e}");
+        std::thread::sleep(std::time::Duration::from_secs(2));
+        return Ok(());
+    }
 
     println!("✅ Task '{slug}' added successfully!");
 
-    // Restart daemon
-    if let Err(e) = crate::daemon::restart_daemon().await {
-        println!("⚠️  Warning: Failed to restart daemon: {e}");
+    // Reload daemon
+    if let Err(e) = crate::daemon::reload_daemon().await {
+        println!("⚠️  Warning: Failed to reload daemon: {e}");
     }
 
     std::thread::sleep(std::time::Duration::from_secs(2));
@@ -686,6 +720,10 @@ async fn add_task_interactive(storage: &mut Storage) -> Result<()> {
             "• Command to execute",
             new_style!(color_fg: {tui_color!(200, 200, 200)}),
         )],
+        inline_vec![ast(
+            "• Timeout (optional, e.g., '30s' or '5m')",
+            new_style!(color_fg: {tui_color!(200, 200, 200)}),
+        )],
         inline_vec![]
     ];
 
@@ -737,8 +775,8 @@ async fn add_task_interactive(storage: &mut Storage) -> Result<()> {
     let cron = cron.trim().to_string();
 
     // Validate cron
-    if let Err(e) = cron::Schedule::from_str(&cron) {
-        println!("Error: Invalid cron expression: {e}");
+    if let Err(e) = schedule::TaskSchedule::new(&cron, None) {
+        println!("Error: {e}");
         std::thread::sleep(std::time::Duration::from_secs(2));
         return Ok(());
     }
@@ -756,25 +794,52 @@ async fn add_task_interactive(storage: &mut Storage) -> Result<()> {
         return Ok(());
     }
 
+    // Get optional timeout
+    print!("Enter timeout (e.g., '30s', '5m'; empty for none): ");
+    std::io::Write::flush(&mut std::io::stdout())?;
+    let mut timeout = String::new();
+    std::io::stdin().read_line(&mut timeout)?;
+    let timeout = timeout.trim();
+
+    let timeout = if timeout.is_empty() {
+        None
+    } else {
+        match crate::cli::parse_duration(timeout) {
+            Ok(secs) => Some(secs),
+            Err(e) => {
+                println!("Error: {e}");
+                std::thread::sleep(std::time::Duration::from_secs(2));
+                return Ok(());
+            }
+        }
+    };
+
     // Create and save task
     let event = Event {
         slug: slug.clone(),
         cron,
         command,
-        pid: None,
-        created_at: chrono::Utc::now(),
-        last_run: None,
-        active: true,
+        // Commands typed into the TUI are command lines, so let the shell parse them
+        shell: Some(DEFAULT_SHELL.to_string()),
+        timeout,
+        ..Default::default()
     };
 
-    storage.events.push(event);
-    storage.save().await?;
+    // Re-checked under the storage lock in case another process added it
+    if let Err(e) = storage
+        .update_in_place(|storage| storage.insert_event(event))
+        .await
+    {
+        println!("Error: {e}");
+        std::thread::sleep(std::time::Duration::from_secs(2));
+        return Ok(());
+    }
 
     println!("✅ Task '{slug}' added successfully!");
 
-    // Restart daemon
-    if let Err(e) = crate::daemon::restart_daemon().await {
-        println!("⚠️  Warning: Failed to restart daemon: {e}");
+    // Reload daemon
+    if let Err(e) = crate::daemon::reload_daemon().await {
+        println!("⚠️  Warning: Failed to reload daemon: {e}");
     }
 
     std::thread::sleep(std::time::Duration::from_secs(2));
@@ -870,8 +935,13 @@ async fn delete_task_with_readline(
     if let Some(dot_pos) = selected_str.find('.') {
         if let Ok(index) = selected_str[..dot_pos].trim().parse::<usize>() {
             if index > 0 && index <= storage.events.len() {
-                let task = storage.events.remove(index - 1);
-                storage.save().await?;
+                let task = storage.events[index - 1].clone();
+                storage
+                    .update_in_place(|storage| {
+                        storage.events.retain(|e| e.slug != task.slug);
+                        Ok(())
+                    })
+                    .await?;
 
                 // Show success message
                 let success_header = ast_lines![inline_vec![ast(
@@ -894,10 +964,10 @@ async fn delete_task_with_readline(
                 .await
                 .map_err(|e| anyhow::anyhow!("Choose error: {e}"))?;
 
-                // Restart daemon if needed
+                // Reload daemon if needed
                 if storage.events.iter().any(|e| e.active) {
-                    if let Err(e) = crate::daemon::restart_daemon().await {
-                        eprintln!("Warning: Failed to restart daemon: {e}");
+                    if let Err(e) = crate::daemon::reload_daemon().await {
+                        eprintln!("Warning: Failed to reload daemon: {e}");
                     }
                 } else if let Err(e) = crate::daemon::stop_daemon().await {
                     eprintln!("Warning: Failed to stop daemon: {e}");
@@ -992,8 +1062,13 @@ async fn delete_task_interactive_with_choose(storage: &mut Storage) -> Result<()
     if let Some(dot_pos) = selected_str.find('.') {
         if let Ok(index) = selected_str[..dot_pos].trim().parse::<usize>() {
             if index > 0 && index <= storage.events.len() {
-                let task = storage.events.remove(index - 1);
-                storage.save().await?;
+                let task = storage.events[index - 1].clone();
+                storage
+                    .update_in_place(|storage| {
+                        storage.events.retain(|e| e.slug != task.slug);
+                        Ok(())
+                    })
+                    .await?;
 
                 // Show success message
                 let success_header = ast_lines![inline_vec![ast(
@@ -1019,10 +1094,10 @@ async fn delete_task_interactive_with_choose(storage: &mut Storage) -> Result<()
                 .await
                 .map_err(|e| anyhow::anyhow!("Choose error: {e}"))?;
 
-                // Restart daemon if needed
+                // Reload daemon if needed
                 if storage.events.iter().any(|e| e.active) {
-                    if let Err(e) = crate::daemon::restart_daemon().await {
-                        eprintln!("Warning: Failed to restart daemon: {e}");
+                    if let Err(e) = crate::daemon::reload_daemon().await {
+                        eprintln!("Warning: Failed to reload daemon: {e}");
                     }
                 } else if let Err(e) = crate::daemon::stop_daemon().await {
                     eprintln!("Warning: Failed to stop daemon: {e}");
@@ -1115,8 +1190,13 @@ async fn delete_task_interactive(storage: &mut Storage) -> Result<()> {
     if let Some(dot_pos) = selected_str.find('.') {
         if let Ok(index) = selected_str[..dot_pos].trim().parse::<usize>() {
             if index > 0 && index <= storage.events.len() {
-                let task = storage.events.remove(index - 1);
-                storage.save().await?;
+                let task = storage.events[index - 1].clone();
+                storage
+                    .update_in_place(|storage| {
+                        storage.events.retain(|e| e.slug != task.slug);
+                        Ok(())
+                    })
+                    .await?;
 
                 // Show success message
                 let success_header = ast_lines![inline_vec![ast(
@@ -1141,10 +1221,10 @@ async fn delete_task_interactive(storage: &mut Storage) -> Result<()> {
                 .await
                 .map_err(|e| anyhow::anyhow!("Choose error: {e}"))?;
 
-                // Restart daemon if needed
+                // Reload daemon if needed
                 if storage.events.iter().any(|e| e.active) {
-                    if let Err(e) = crate::daemon::restart_daemon().await {
-                        eprintln!("Warning: Failed to restart daemon: {e}");
+                    if let Err(e) = crate::daemon::reload_daemon().await {
+                        eprintln!("Warning: Failed to reload daemon: {e}");
                     }
                 } else if let Err(e) = crate::daemon::stop_daemon().await {
                     eprintln!("Warning: Failed to stop daemon: {e}");
@@ -1246,16 +1326,19 @@ async fn toggle_task_with_readline(
     if let Some(dot_pos) = selected_str.find('.') {
         if let Ok(index) = selected_str[..dot_pos].trim().parse::<usize>() {
             if index > 0 && index <= storage.events.len() {
-                let task = &mut storage.events[index - 1];
-                task.active = !task.active;
-                let new_status = if task.active {
-                    "activated"
-                } else {
-                    "deactivated"
-                };
-                let slug = task.slug.clone();
-
-                storage.save().await?;
+                let slug = storage.events[index - 1].slug.clone();
+                let active = storage
+                    .update_in_place(|storage| {
+                        let task = storage
+                            .events
+                            .iter_mut()
+                            .find(|e| e.slug == slug)
+                            .ok_or_else(|| anyhow::anyhow!("Task '{}' no longer exists", slug))?;
+                        task.active = !task.active;
+                        Ok(task.active)
+                    })
+                    .await?;
+                let new_status = if active { "activated" } else { "deactivated" };
 
                 // Show success message
                 let success_header = ast_lines![inline_vec![ast(
@@ -1278,9 +1361,9 @@ async fn toggle_task_with_readline(
                 .await
                 .map_err(|e| anyhow::anyhow!("Choose error: {e}"))?;
 
-                // Restart daemon
-                if let Err(e) = crate::daemon::restart_daemon().await {
-                    eprintln!("Warning: Failed to restart daemon: {e}");
+                // Reload daemon
+                if let Err(e) = crate::daemon::reload_daemon().await {
+                    eprintln!("Warning: Failed to reload daemon: {e}");
                 }
             }
         }
@@ -1374,16 +1457,19 @@ async fn toggle_task_interactive_with_choose(storage: &mut Storage) -> Result<()
     if let Some(dot_pos) = selected_str.find('.') {
         if let Ok(index) = selected_str[..dot_pos].trim().parse::<usize>() {
             if index > 0 && index <= storage.events.len() {
-                let task = &mut storage.events[index - 1];
-                task.active = !task.active;
-                let new_status = if task.active {
-                    "activated"
-                } else {
-                    "deactivated"
-                };
-                let slug = task.slug.clone();
-
-                storage.save().await?;
+                let slug = storage.events[index - 1].slug.clone();
+                let active = storage
+                    .update_in_place(|storage| {
+                        let task = storage
+                            .events
+                            .iter_mut()
+                            .find(|e| e.slug == slug)
+                            .ok_or_else(|| anyhow::anyhow!("Task '{}' no longer exists", slug))?;
+                        task.active = !task.active;
+                        Ok(task.active)
+                    })
+                    .await?;
+                let new_status = if active { "activated" } else { "deactivated" };
 
                 // Show success message
                 let success_header = ast_lines![inline_vec![ast(
@@ -1409,9 +1495,9 @@ async fn toggle_task_interactive_with_choose(storage: &mut Storage) -> Result<()
                 .await
                 .map_err(|e| anyhow::anyhow!("Choose error: {e}"))?;
 
-                // Restart daemon
-                if let Err(e) = crate::daemon::restart_daemon().await {
-                    eprintln!("Warning: Failed to restart daemon: {e}");
+                // Reload daemon
+                if let Err(e) = crate::daemon::reload_daemon().await {
+                    eprintln!("Warning: Failed to reload daemon: {e}");
                 }
             }
         }
@@ -1503,16 +1589,19 @@ async fn toggle_task_interactive(storage: &mut Storage) -> Result<()> {
     if let Some(dot_pos) = selected_str.find('.') {
         if let Ok(index) = selected_str[..dot_pos].trim().parse::<usize>() {
             if index > 0 && index <= storage.events.len() {
-                let task = &mut storage.events[index - 1];
-                task.active = !task.active;
-                let new_status = if task.active {
-                    "activated"
-                } else {
-                    "deactivated"
-                };
-                let slug = task.slug.clone();
-
-                storage.save().await?;
+                let slug = storage.events[index - 1].slug.clone();
+                let active = storage
+                    .update_in_place(|storage| {
+                        let task = storage
+                            .events
+                            .iter_mut()
+                            .find(|e| e.slug == slug)
+                            .ok_or_else(|| anyhow::anyhow!("Task '{}' no longer exists", slug))?;
+                        task.active = !task.active;
+                        Ok(task.active)
+                    })
+                    .await?;
+                let new_status = if active { "activated" } else { "deactivated" };
 
                 // Show success message
                 let success_header = ast_lines![inline_vec![ast(
@@ -1537,9 +1626,9 @@ async fn toggle_task_interactive(storage: &mut Storage) -> Result<()> {
                 .await
                 .map_err(|e| anyhow::anyhow!("Choose error: {e}"))?;
 
-                // Restart daemon
-                if let Err(e) = crate::daemon::restart_daemon().await {
-                    eprintln!("Warning: Failed to restart daemon: {e}");
+                // Reload daemon
+                if let Err(e) = crate::daemon::reload_daemon().await {
+                    eprintln!("Warning: Failed to reload daemon: {e}");
                 }
             }
         }
@@ -1548,6 +1637,19 @@ async fn toggle_task_interactive(storage: &mut Storage) -> Result<()> {
     Ok(())
 }
 
+/// Last and next run of a task in local time.
+fn run_times(event: &Event) -> String {
+    let last = event
+        .last_run
+        .map(schedule::format_local)
+        .unwrap_or_else(|| "never".to_string());
+    let next = schedule::next_run(event, chrono::Utc::now())
+        .map(schedule::format_local)
+        .unwrap_or_else(|| "-".to_string());
+
+    format!("last: {last:<16}  next: {next}")
+}
+
 fn truncate(s: &str, max_len: usize) -> String {
     if s.len() > max_len {
         format!("{}...", &s[..max_len.saturating_sub(3)])
@@ -1555,5 +1657,3 @@ fn truncate(s: &str, max_len: usize) -> String {
         s.to_string()
     }
 }
-
-use std::str::FromStr;
@@ -86,10 +86,8 @@ impl NewTaskInput {
             slug: self.slug.clone(),
             cron: self.cron.clone(),
             command: self.command.clone(),
-            pid: None,
-            created_at: chrono::Utc::now(),
-            last_run: None,
-            active: true,
+            shell: Some(crate::storage::DEFAULT_SHELL.to_string()),
+            ..Default::default()
         })
     }
 }
@@ -0,0 +1,20 @@
+use singleschedule::{daemon, paths};
+use std::env;
+use tempfile::TempDir;
+
+// Tests in one binary share the process environment, so the ones that point
+// SINGLESCHEDULE_HOME at a temp dir take turns, like the unit tests in lib.rs.
+static TEST_HOME_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Point the CLI handlers at a fresh data directory, with daemon management
+/// turned off, until the returned guard is dropped.
+pub async fn test_home() -> (TempDir, tokio::sync::MutexGuard<'static, ()>) {
+    let guard = TEST_HOME_LOCK.lock().await;
+    let temp_dir = TempDir::new().unwrap();
+    unsafe {
+        env::set_var(paths::HOME_ENV, temp_dir.path());
+        env::set_var(daemon::NO_DAEMON_ENV, "1");
+        env::remove_var(paths::PROFILE_ENV);
+    }
+    (temp_dir, guard)
+}
@@ -1,20 +1,25 @@
-use singleschedule::{cli, storage::Storage};
+// Lints newer than some of these tests
+#![allow(clippy::needless_borrows_for_generic_args)]
+
+use singleschedule::history::{History, RunRecord, RunStatus};
+use singleschedule::{cli, daemon, paths, schedule, storage::Storage};
 use std::env;
 use std::process::Command;
 use tempfile::TempDir;
 
+mod common;
+use common::test_home;
+
 #[tokio::test]
 async fn test_end_to_end_workflow() {
-    let temp_dir = TempDir::new().unwrap();
-    unsafe {
-        env::set_var("SINGLESCHEDULE_TEST_HOME", temp_dir.path());
-    }
+    let (_temp_dir, _guard) = test_home().await;
 
     // Add a task
     cli::handle_add(
         "test-task".to_string(),
         "* * * * * *".to_string(),
         vec!["echo".to_string(), "hello world".to_string()],
+        cli::AddOptions::default(),
     )
     .await
     .unwrap();
@@ -25,7 +30,7 @@ async fn test_end_to_end_workflow() {
     assert_eq!(storage.events[0].slug, "test-task");
 
     // List tasks
-    cli::handle_list().await.unwrap();
+    cli::handle_list(false).await.unwrap();
 
     // Remove the task
     cli::handle_remove("test-task".to_string()).await.unwrap();
@@ -37,10 +42,7 @@ async fn test_end_to_end_workflow() {
 
 #[tokio::test]
 async fn test_multiple_tasks() {
-    let temp_dir = TempDir::new().unwrap();
-    unsafe {
-        env::set_var("SINGLESCHEDULE_TEST_HOME", temp_dir.path());
-    }
+    let (_temp_dir, _guard) = test_home().await;
 
     // Add multiple tasks
     for i in 1..=5 {
@@ -48,6 +50,7 @@ async fn test_multiple_tasks() {
             format!("task-{}", i),
             "0 * * * * *".to_string(),
             vec!["echo".to_string(), format!("task {}", i)],
+            cli::AddOptions::default(),
         )
         .await
         .unwrap();
@@ -67,10 +70,7 @@ async fn test_multiple_tasks() {
 
 #[tokio::test]
 async fn test_complex_commands() {
-    let temp_dir = TempDir::new().unwrap();
-    unsafe {
-        env::set_var("SINGLESCHEDULE_TEST_HOME", temp_dir.path());
-    }
+    let (_temp_dir, _guard) = test_home().await;
 
     // Test command with multiple arguments and flags
     cli::handle_add(
@@ -86,6 +86,7 @@ async fn test_complex_commands() {
             "{\"status\": \"ok\"}".to_string(),
             "http://example.com/webhook".to_string(),
         ],
+        cli::AddOptions::default(),
     )
     .await
     .unwrap();
@@ -95,6 +96,23 @@ async fn test_complex_commands() {
     assert!(storage.events[0].command.contains("curl"));
     assert!(storage.events[0].command.contains("POST"));
     assert!(storage.events[0].command.contains("example.com"));
+
+    // The exact tokens are kept so arguments with spaces survive
+    let argv = storage.events[0].argv.as_ref().unwrap();
+    assert_eq!(argv.len(), 8);
+    assert_eq!(argv[4], "Content-Type: application/json");
+    assert_eq!(argv[6], "{\"status\": \"ok\"}");
+
+    // Long commands are cut at a character boundary in the list
+    cli::handle_add(
+        "non-ascii".to_string(),
+        "@daily".to_string(),
+        vec!["cat".to_string(), "ü".repeat(40)],
+        cli::AddOptions::default(),
+    )
+    .await
+    .unwrap();
+    cli::handle_list(false).await.unwrap();
 }
 
 #[test]
@@ -123,10 +141,7 @@ fn test_cli_binary() {
 
 #[tokio::test]
 async fn test_selective_start_stop() {
-    let temp_dir = TempDir::new().unwrap();
-    unsafe {
-        env::set_var("SINGLESCHEDULE_TEST_HOME", temp_dir.path());
-    }
+    let (_temp_dir, _guard) = test_home().await;
 
     // Add multiple tasks
     for i in 1..=3 {
@@ -134,6 +149,7 @@ async fn test_selective_start_stop() {
             format!("task-{}", i),
             "0 * * * * *".to_string(),
             vec!["echo".to_string(), format!("task {}", i)],
+            cli::AddOptions::default(),
         )
         .await
         .unwrap();
@@ -218,16 +234,14 @@ async fn test_selective_start_stop() {
 
 #[tokio::test]
 async fn test_start_stop_nonexistent_task() {
-    let temp_dir = TempDir::new().unwrap();
-    unsafe {
-        env::set_var("SINGLESCHEDULE_TEST_HOME", temp_dir.path());
-    }
+    let (_temp_dir, _guard) = test_home().await;
 
     // Add a task
     cli::handle_add(
         "existing-task".to_string(),
         "0 * * * * *".to_string(),
         vec!["echo".to_string(), "hello".to_string()],
+        cli::AddOptions::default(),
     )
     .await
     .unwrap();
@@ -259,3 +273,237 @@ async fn test_start_stop_nonexistent_task() {
     .await;
     assert!(result.is_err());
 }
+
+#[test]
+fn test_run_in_foreground() {
+    let temp_dir = TempDir::new().unwrap();
+    let socket_path = temp_dir.path().join("notify.sock");
+    let socket = std::os::unix::net::UnixDatagram::bind(&socket_path).unwrap();
+    socket
+        .set_read_timeout(Some(std::time::Duration::from_secs(10)))
+        .unwrap();
+    let receive = || {
+        let mut buf = [0; 256];
+        let len = socket.recv(&mut buf).unwrap();
+        String::from_utf8_lossy(&buf[..len]).into_owned()
+    };
+
+    let home = temp_dir.path().join("home");
+    let env_file = home.join("task-env.txt");
+    let added = Command::new(env!("CARGO_BIN_EXE_singleschedule"))
+        .args([
+            "add",
+            "--slug",
+            "dump-env",
+            "--cron",
+            "* * * * * *",
+            "--shell",
+            "--",
+        ])
+        .arg(format!("env > {}", env_file.display()))
+        .env(paths::HOME_ENV, &home)
+        .env(daemon::NO_DAEMON_ENV, "1")
+        .status()
+        .unwrap();
+    assert!(added.success());
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_singleschedule"))
+        .args(["run", "--foreground", "--pid-file"])
+        .env(paths::HOME_ENV, &home)
+        .env(singleschedule::sd_notify::NOTIFY_SOCKET_ENV, &socket_path)
+        .env("WATCHDOG_USEC", "200000")
+        .spawn()
+        .unwrap();
+
+    let ready = receive();
+    assert!(ready.starts_with("READY=1\n"), "{ready}");
+    assert!(ready.contains(&format!("MAINPID={}", child.id())));
+    assert_eq!(receive(), "WATCHDOG=1");
+    let pid_file = home.join("daemon.pid");
+    assert_eq!(
+        std::fs::read_to_string(&pid_file).unwrap().trim(),
+        child.id().to_string()
+    );
+
+    // Tasks don't inherit the scheduler's service manager variables
+    for _ in 0..50 {
+        if env_file.exists() && !std::fs::read_to_string(&env_file).unwrap().is_empty() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    let task_env = std::fs::read_to_string(&env_file).unwrap();
+    assert!(task_env.contains("PATH="), "{task_env}");
+    assert!(!task_env.contains("NOTIFY_SOCKET="), "{task_env}");
+    assert!(!task_env.contains("WATCHDOG_USEC="), "{task_env}");
+
+    nix::sys::signal::kill(
+        nix::unistd::Pid::from_raw(child.id() as i32),
+        nix::sys::signal::Signal::SIGTERM,
+    )
+    .unwrap();
+    let stopping = std::iter::repeat_with(receive)
+        .find(|message| message != "WATCHDOG=1")
+        .unwrap();
+    assert_eq!(stopping, "STOPPING=1");
+    assert!(child.wait().unwrap().success());
+    assert!(!pid_file.exists());
+}
+
+#[test]
+fn test_daemon_reports_startup() {
+    let temp_dir = TempDir::new().unwrap();
+    let singleschedule = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_singleschedule"))
+            .args(args)
+            .env(paths::HOME_ENV, temp_dir.path())
+            .env_remove(daemon::NO_DAEMON_ENV)
+            .env_remove(paths::PROFILE_ENV)
+            .env("RUST_BACKTRACE", "0")
+            .output()
+            .unwrap()
+    };
+
+    // The daemon fails to load its tasks, and says so through `run`
+    std::fs::write(temp_dir.path().join("events.json"), "garbage").unwrap();
+    let output = singleschedule(&["run"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Failed to start daemon"), "{stderr}");
+    assert!(!temp_dir.path().join("daemon.pid").exists());
+
+    std::fs::remove_file(temp_dir.path().join("events.json")).unwrap();
+    let output = singleschedule(&["run"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(temp_dir.path().join("daemon.pid").exists());
+    assert!(!singleschedule(&["run"]).status.success());
+
+    // The CLI talks to the daemon over its control socket
+    let output = singleschedule(&["status"]);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Daemon running with PID"), "{stdout}");
+    assert!(!singleschedule(&["trigger", "missing"]).status.success());
+
+    let output = singleschedule(&["stop"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(!temp_dir.path().join("daemon.pid").exists());
+    assert!(!temp_dir.path().join("control.sock").exists());
+    assert!(!singleschedule(&["status"]).status.success());
+}
+
+#[tokio::test]
+async fn test_history_and_logs() {
+    let (_temp_dir, _guard) = test_home().await;
+    let singleschedule = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_singleschedule"))
+            .args(args)
+            .env("RUST_BACKTRACE", "0")
+            .output()
+            .unwrap()
+    };
+    let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+
+    cli::handle_add(
+        "logged".to_string(),
+        "@daily".to_string(),
+        vec!["echo".to_string(), "hi".to_string()],
+        cli::AddOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    let output = singleschedule(&["history", "logged"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(text(&output.stdout).contains("No runs recorded for task 'logged'"));
+    let output = singleschedule(&["history", "missing"]);
+    assert!(!output.status.success());
+    assert!(text(&output.stderr).contains("Task with slug 'missing' not found"));
+
+    let mut started = Vec::new();
+    for stdout in ["first\n", "second\n"] {
+        let run = RunRecord {
+            status: RunStatus::Succeeded,
+            exit_code: Some(0),
+            duration_ms: Some(1500),
+            stdout: stdout.to_string(),
+            ..RunRecord::started("logged", chrono::Utc::now())
+        };
+        started.push(History::record(run, 10).await.unwrap().started_at);
+    }
+
+    // Newest first, in local time
+    let output = singleschedule(&["history", "logged"]);
+    let stdout = text(&output.stdout);
+    let rows: Vec<&str> = stdout.lines().skip(2).collect();
+    assert_eq!(rows.len(), 2);
+    assert!(rows[0].starts_with("2 ") && rows[1].starts_with("1 "));
+    assert!(rows[1].contains(&schedule::format_local(started[0])));
+    assert!(rows[0].contains("succeeded") && rows[0].contains("1.5s"));
+
+    assert_eq!(
+        text(&singleschedule(&["logs", "logged"]).stdout),
+        "second\n"
+    );
+    let output = singleschedule(&["logs", "logged", "--run", "1"]);
+    assert_eq!(text(&output.stdout), "first\n");
+    let output = singleschedule(&["logs", "logged", "--run", "9"]);
+    assert!(!output.status.success());
+    assert!(text(&output.stderr).contains("Run 9 of task 'logged' not found"));
+
+    // Follow a run in progress until the scheduler records it as finished
+    let mut scheduler = Command::new(env!("CARGO_BIN_EXE_singleschedule"))
+        .args(["run", "--foreground"])
+        .env("RUST_LOG", "off")
+        .spawn()
+        .unwrap();
+    for _ in 0..100 {
+        if daemon::is_running().await.unwrap() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    let mut run = History::record(RunRecord::started("logged", chrono::Utc::now()), 10)
+        .await
+        .unwrap();
+    let live_log = History::live_log_path("logged", run.id).unwrap();
+    std::fs::write(&live_log, "partial\n").unwrap();
+
+    let follow = Command::new(env!("CARGO_BIN_EXE_singleschedule"))
+        .args(["logs", "logged", "--follow"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(700)).await;
+
+    let mut log = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&live_log)
+        .unwrap();
+    std::io::Write::write_all(&mut log, b"rest\n").unwrap();
+    run.status = RunStatus::Succeeded;
+    run.exit_code = Some(0);
+    run.stdout = "partial\nrest\n".to_string();
+    History::update(&run).await.unwrap();
+    std::fs::remove_file(&live_log).unwrap();
+
+    let output = follow.wait_with_output().unwrap();
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(text(&output.stdout), "partial\nrest\n");
+    assert!(text(&output.stderr).contains("Run 3 succeeded"));
+
+    // A scheduler that dies mid-run never records the end; stop following
+    let run = History::record(RunRecord::started("logged", chrono::Utc::now()), 10)
+        .await
+        .unwrap();
+    let live_log = History::live_log_path("logged", run.id).unwrap();
+    std::fs::write(&live_log, "cut off\n").unwrap();
+    scheduler.kill().unwrap();
+    scheduler.wait().unwrap();
+
+    let output = singleschedule(&["logs", "logged", "--follow"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(text(&output.stdout), "cut off\n");
+    assert!(text(&output.stderr).contains("Run 4 interrupted"));
+}
@@ -1,10 +1,14 @@
+// Lints newer than some of these tests
+#![allow(clippy::assertions_on_constants)]
+
 use chrono::{Duration, Utc};
 use singleschedule::{
     scheduler::Scheduler,
     storage::{Event, Storage},
 };
-use std::env;
-use tempfile::TempDir;
+
+mod common;
+use common::test_home;
 
 #[tokio::test]
 async fn test_scheduler_initialization() {
@@ -15,10 +19,7 @@ async fn test_scheduler_initialization() {
 
 #[tokio::test]
 async fn test_scheduler_load_events() {
-    let temp_dir = TempDir::new().unwrap();
-    unsafe {
-        env::set_var("SINGLESCHEDULE_TEST_HOME", temp_dir.path());
-    }
+    let (_temp_dir, _guard) = test_home().await;
 
     // Create storage with events
     let mut storage = Storage::new();
@@ -30,6 +31,7 @@ async fn test_scheduler_load_events() {
         created_at: Utc::now(),
         last_run: None,
         active: true,
+        ..Default::default()
     });
 
     storage.events.push(Event {
@@ -40,6 +42,7 @@ async fn test_scheduler_load_events() {
         created_at: Utc::now(),
         last_run: None,
         active: true,
+        ..Default::default()
     });
 
     storage.save().await.unwrap();
@@ -71,6 +74,7 @@ async fn test_should_run_logic() {
         created_at: now - Duration::hours(1),
         last_run: None,
         active: true,
+        ..Default::default()
     };
 
     // Task that ran 2 minutes ago should run again
@@ -82,6 +86,7 @@ async fn test_should_run_logic() {
         created_at: now - Duration::hours(1),
         last_run: Some(now - Duration::minutes(2)),
         active: true,
+        ..Default::default()
     };
 
     // Task that just ran should not run again
@@ -93,15 +98,13 @@ async fn test_should_run_logic() {
         created_at: now - Duration::hours(1),
         last_run: Some(now - Duration::seconds(30)),
         active: true,
+        ..Default::default()
     };
 }
 
 #[tokio::test]
 async fn test_invalid_cron_handling() {
-    let temp_dir = TempDir::new().unwrap();
-    unsafe {
-        env::set_var("SINGLESCHEDULE_TEST_HOME", temp_dir.path());
-    }
+    let (_temp_dir, _guard) = test_home().await;
 
     let mut storage = Storage::new();
     storage.events.push(Event {
@@ -112,6 +115,7 @@ async fn test_invalid_cron_handling() {
         created_at: Utc::now(),
         last_run: None,
         active: true,
+        ..Default::default()
     });
 
     storage.save().await.unwrap();